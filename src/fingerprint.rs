@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::fs::Metadata;
+use std::time::UNIX_EPOCH;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64 bit FNV-1a hash, stable across platforms and releases.
+pub fn content_hash(content: &[u8]) -> u64 {
+    content.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// State of a file at the time it was indexed, used to detect changes on refresh.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct FileFingerprint {
+    /// Nanoseconds since the unix epoch
+    pub modified: u64,
+    pub size: u64,
+    pub content_hash: u64,
+}
+
+/// Modification time of a file in nanoseconds since the unix epoch, 0 when unavailable.
+pub fn modification_time(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+        .unwrap_or_default()
+}
+
+impl FileFingerprint {
+    pub fn new(metadata: &Metadata, content: &[u8]) -> Self {
+        Self {
+            modified: modification_time(metadata),
+            size: metadata.len(),
+            content_hash: content_hash(content),
+        }
+    }
+
+    /// Whether the file metadata still matches, in which case its content is not read again.
+    pub fn matches_metadata(&self, metadata: &Metadata) -> bool {
+        self.modified == modification_time(metadata) && self.size == metadata.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::content_hash;
+
+    #[test]
+    fn content_hash_is_stable() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(content_hash(b"rome"), content_hash(b"roma"));
+    }
+}
@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob::Glob;
+
+/// Ignore files read in every directory, later files take precedence over earlier ones.
+pub const IGNORE_FILENAMES: [&str; 3] = [".gitignore", ".ignore", ".trustamiignore"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IgnoreMatch {
+    /// No rule applies to the path
+    None,
+    Ignore,
+    /// A negated rule re-included the path
+    Whitelist,
+}
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    glob: Glob,
+    negated: bool,
+    directory_only: bool,
+}
+
+/// Rules of the ignore files found in one directory, following gitignore semantics:
+/// patterns containing a `/` are anchored to that directory, a trailing `/` only matches
+/// directories, `!` re-includes paths and the last matching rule wins.
+#[derive(Debug, Clone)]
+pub struct Gitignore {
+    directory: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl Gitignore {
+    pub fn parse<P>(directory: P, contents: &str) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let rules = contents.lines().filter_map(parse_rule).collect();
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            rules,
+        }
+    }
+
+    /// Reads every ignore file of a directory, `None` when there is none.
+    pub fn from_directory<P>(directory: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let directory = directory.as_ref();
+        let contents: Vec<String> = IGNORE_FILENAMES
+            .iter()
+            .filter_map(|filename| fs::read_to_string(directory.join(filename)).ok())
+            .collect();
+        if contents.is_empty() {
+            return None;
+        }
+        Some(Self::parse(directory, &contents.join("\n")))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn matched(&self, path: &Path, is_directory: bool) -> IgnoreMatch {
+        let Ok(relative) = path.strip_prefix(&self.directory) else {
+            return IgnoreMatch::None;
+        };
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_directory || !rule.directory_only) && rule.glob.is_match(&relative))
+            .map_or(IgnoreMatch::None, |rule| {
+                if rule.negated {
+                    IgnoreMatch::Whitelist
+                } else {
+                    IgnoreMatch::Ignore
+                }
+            })
+    }
+}
+
+fn parse_rule(line: &str) -> Option<IgnoreRule> {
+    if line.starts_with('#') {
+        return None;
+    }
+
+    // trailing spaces are ignored unless escaped
+    let mut line = line.trim_end_matches(['\r', '\n']);
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    if line.is_empty() {
+        return None;
+    }
+
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (directory_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    let glob = Glob::new(line).ok()?;
+    Some(IgnoreRule {
+        glob,
+        negated,
+        directory_only,
+    })
+}
+
+/// Whether a path is ignored by a stack of ignore files ordered from the root down.
+/// Files in deeper directories take precedence over the ones above them.
+pub fn is_ignored<'a, I>(stack: I, path: &Path, is_directory: bool) -> bool
+where
+    I: DoubleEndedIterator<Item = &'a Gitignore>,
+{
+    for gitignore in stack.rev() {
+        match gitignore.matched(path, is_directory) {
+            IgnoreMatch::Ignore => return true,
+            IgnoreMatch::Whitelist => return false,
+            IgnoreMatch::None => (),
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::gitignore::{Gitignore, IgnoreMatch, is_ignored};
+
+    fn matched(contents: &str, path: &str, is_directory: bool) -> IgnoreMatch {
+        Gitignore::parse("/repo", contents).matched(&Path::new("/repo").join(path), is_directory)
+    }
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        assert_eq!(
+            matched("*.log", "a/b/debug.log", false),
+            IgnoreMatch::Ignore
+        );
+        assert_eq!(
+            matched("target", "crates/x/target", true),
+            IgnoreMatch::Ignore
+        );
+    }
+
+    #[test]
+    fn anchored_patterns_match_from_the_ignore_file_directory() {
+        assert_eq!(matched("/build", "build", true), IgnoreMatch::Ignore);
+        assert_eq!(matched("/build", "src/build", true), IgnoreMatch::None);
+        assert_eq!(
+            matched("doc/frotz", "doc/frotz", false),
+            IgnoreMatch::Ignore
+        );
+        assert_eq!(
+            matched("doc/frotz", "a/doc/frotz", false),
+            IgnoreMatch::None
+        );
+    }
+
+    #[test]
+    fn directory_only_rules_skip_files() {
+        assert_eq!(matched("out/", "out", true), IgnoreMatch::Ignore);
+        assert_eq!(matched("out/", "out", false), IgnoreMatch::None);
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let contents = "*.md\n!README.md\n# comment\n\n";
+
+        assert_eq!(matched(contents, "notes.md", false), IgnoreMatch::Ignore);
+        assert_eq!(
+            matched(contents, "README.md", false),
+            IgnoreMatch::Whitelist
+        );
+        assert_eq!(
+            matched("!README.md\n*.md", "README.md", false),
+            IgnoreMatch::Ignore
+        );
+    }
+
+    #[test]
+    fn escapes_and_trailing_spaces() {
+        assert_eq!(matched("\\#notes  ", "#notes", false), IgnoreMatch::Ignore);
+        assert_eq!(
+            matched("\\!important", "!important", false),
+            IgnoreMatch::Ignore
+        );
+    }
+
+    #[test]
+    fn deeper_ignore_files_take_precedence() {
+        let stack = [
+            Gitignore::parse("/repo", "*.txt"),
+            Gitignore::parse("/repo/docs", "!keep.txt"),
+        ];
+
+        assert!(is_ignored(
+            stack.iter(),
+            Path::new("/repo/notes.txt"),
+            false
+        ));
+        assert!(is_ignored(
+            stack.iter(),
+            Path::new("/repo/docs/other.txt"),
+            false
+        ));
+        assert!(!is_ignored(
+            stack.iter(),
+            Path::new("/repo/docs/keep.txt"),
+            false
+        ));
+    }
+}
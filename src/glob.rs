@@ -0,0 +1,349 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum GlobError {
+    #[error("Unclosed character class in pattern {0}")]
+    UnclosedClass(String),
+    #[error("Unclosed alternative group in pattern {0}")]
+    UnclosedAlternatives(String),
+    #[error("Empty pattern")]
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    /// `?`, any character but a separator
+    One,
+    /// `*`, any run of characters within a path segment
+    Any,
+    /// `**/`, zero or more whole directories
+    AnyDirectories,
+    /// `**` not followed by a separator, anything including separators
+    AnyPath,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A shell style pattern matched against `/` separated relative paths.
+///
+/// Supports `*`, `?`, `**`, character classes (`[abc]`, `[a-z]`, `[!a]`), alternatives
+/// (`{md,txt}`) and `\` escapes. A pattern without any `/` is matched against the file name
+/// only, so `*.md` selects Markdown files at any depth.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    alternatives: Vec<Vec<Token>>,
+    basename_only: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        if pattern.is_empty() {
+            return Err(GlobError::Empty);
+        }
+        let trimmed = pattern.strip_prefix("./").unwrap_or(pattern);
+        let basename_only = !trimmed.contains('/');
+        let trimmed = trimmed.strip_prefix('/').unwrap_or(trimmed);
+
+        let alternatives = expand_alternatives(trimmed)?
+            .iter()
+            .map(|expanded| tokenize(expanded, pattern))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            alternatives,
+            basename_only,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether the pattern consists only of a file name, without any directory part.
+    pub fn is_basename_only(&self) -> bool {
+        self.basename_only
+    }
+
+    /// Matches a relative path using `/` as separator.
+    pub fn is_match(&self, path: &str) -> bool {
+        let subject = if self.basename_only {
+            path.rsplit('/').next().unwrap_or(path)
+        } else {
+            path
+        };
+        let chars: Vec<char> = subject.chars().collect();
+        self.alternatives
+            .iter()
+            .any(|tokens| matches(tokens, &chars, 0, 0, &mut HashSet::new()))
+    }
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Serialize for Glob {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for Glob {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        Glob::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Expands `{a,b}` groups into one pattern per alternative.
+fn expand_alternatives(pattern: &str) -> Result<Vec<String>, GlobError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut escaped = false;
+    let mut open = None;
+    let mut depth = 0;
+
+    for (i, c) in chars.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let start = open.unwrap_or_default();
+                    let prefix: String = chars[..start].iter().collect();
+                    let suffix: String = chars[i + 1..].iter().collect();
+                    let mut expanded = Vec::new();
+                    for alternative in split_alternatives(&chars[start + 1..i]) {
+                        let combined = format!("{prefix}{alternative}{suffix}");
+                        expanded.extend(expand_alternatives(&combined)?);
+                    }
+                    return Ok(expanded);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if depth > 0 {
+        return Err(GlobError::UnclosedAlternatives(pattern.to_string()));
+    }
+    Ok(vec![pattern.to_string()])
+}
+
+/// Splits the content of a `{}` group on its top level commas.
+fn split_alternatives(chars: &[char]) -> Vec<String> {
+    let mut alternatives = vec![String::new()];
+    let mut escaped = false;
+    let mut depth = 0;
+    for c in chars {
+        let current = alternatives
+            .last_mut()
+            .expect("alternatives are never empty");
+        match c {
+            _ if escaped => {
+                escaped = false;
+                current.push(*c);
+                continue;
+            }
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(String::new());
+                continue;
+            }
+            _ => (),
+        }
+        current.push(*c);
+    }
+    alternatives
+}
+
+fn tokenize(pattern: &str, original: &str) -> Result<Vec<Token>, GlobError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Literal(chars[i + 1]));
+                i += 2;
+                continue;
+            }
+            '?' => tokens.push(Token::One),
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_segment_start = i == 0 || chars[i - 1] == '/';
+                if at_segment_start && chars.get(i + 2) == Some(&'/') {
+                    tokens.push(Token::AnyDirectories);
+                    i += 3;
+                } else {
+                    tokens.push(Token::AnyPath);
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => tokens.push(Token::Any),
+            '[' => {
+                let (class, end) = parse_class(&chars, i)
+                    .ok_or_else(|| GlobError::UnclosedClass(original.to_string()))?;
+                tokens.push(class);
+                i = end + 1;
+                continue;
+            }
+            c => tokens.push(Token::Literal(c)),
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Parses the class starting at `start`, returning it with the position of its `]`.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        // a leading `]` is part of the class
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i));
+        }
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|end| *end != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+fn matches(
+    tokens: &[Token],
+    text: &[char],
+    ti: usize,
+    si: usize,
+    failed: &mut HashSet<(usize, usize)>,
+) -> bool {
+    if failed.contains(&(ti, si)) {
+        return false;
+    }
+
+    let result = match tokens.get(ti) {
+        None => si == text.len(),
+        Some(Token::Literal(c)) => {
+            text.get(si) == Some(c) && matches(tokens, text, ti + 1, si + 1, failed)
+        }
+        Some(Token::One) => {
+            text.get(si).is_some_and(|c| *c != '/') && matches(tokens, text, ti + 1, si + 1, failed)
+        }
+        Some(Token::Class { negated, ranges }) => {
+            text.get(si).is_some_and(|c| {
+                *c != '/' && ranges.iter().any(|(low, high)| low <= c && c <= high) != *negated
+            }) && matches(tokens, text, ti + 1, si + 1, failed)
+        }
+        Some(Token::Any) => {
+            let segment_end = text[si..]
+                .iter()
+                .position(|c| *c == '/')
+                .map_or(text.len(), |offset| si + offset);
+            (si..=segment_end).any(|next| matches(tokens, text, ti + 1, next, failed))
+        }
+        Some(Token::AnyPath) => {
+            (si..=text.len()).any(|next| matches(tokens, text, ti + 1, next, failed))
+        }
+        Some(Token::AnyDirectories) => {
+            matches(tokens, text, ti + 1, si, failed)
+                || (si..text.len())
+                    .filter(|&i| text[i] == '/')
+                    .any(|slash| matches(tokens, text, ti + 1, slash + 1, failed))
+        }
+    };
+
+    if !result {
+        failed.insert((ti, si));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glob::{Glob, GlobError};
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn star_stays_within_a_segment() {
+        assert!(is_match("docs/*.md", "docs/intro.md"));
+        assert!(!is_match("docs/*.md", "docs/guide/intro.md"));
+    }
+
+    #[test]
+    fn basename_patterns_match_at_any_depth() {
+        assert!(is_match("*.md", "intro.md"));
+        assert!(is_match("*.md", "docs/guide/intro.md"));
+        assert!(!is_match("*.md", "docs/intro.txt"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(is_match("docs/**/*.md", "docs/intro.md"));
+        assert!(is_match("docs/**/*.md", "docs/a/b/intro.md"));
+        assert!(is_match("docs/**", "docs/a/b/intro.md"));
+        assert!(!is_match("docs/**/*.md", "other/intro.md"));
+    }
+
+    #[test]
+    fn classes_alternatives_and_escapes() {
+        assert!(is_match("te?t.[a-z]xt", "test.txt"));
+        assert!(!is_match("file[!0-9].md", "file1.md"));
+        assert!(is_match("file[!0-9].md", "fileA.md"));
+        assert!(is_match("*.{md,txt}", "notes/todo.txt"));
+        assert!(is_match("\\*.md", "*.md"));
+        assert!(!is_match("\\*.md", "a.md"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(matches!(
+            Glob::new("[abc"),
+            Err(GlobError::UnclosedClass(_))
+        ));
+        assert!(matches!(
+            Glob::new("{a,b"),
+            Err(GlobError::UnclosedAlternatives(_))
+        ));
+        assert_eq!(Glob::new("").unwrap_err(), GlobError::Empty);
+    }
+}
@@ -0,0 +1,878 @@
+use anyhow::{self, Context};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::fingerprint::FileFingerprint;
+use crate::inverse_doc_frequency::{InverseDocumentFrequency, smoothed_idf};
+use crate::inverted_index::{Document, DocumentId, InvertedIndex, Posting};
+use crate::utils::{INDEX_FORMAT_VERSION, Index, IndexReader};
+
+/// First bytes of every binary index file.
+pub const MAGIC: &[u8; 8] = b"TRUSTAMI";
+const HEADER_LENGTH: usize = 48;
+const OFFSET_LENGTH: usize = 8;
+/// Version that introduced the binary format.
+const FIRST_BINARY_VERSION: u32 = 7;
+/// Version from which postings carry token positions.
+pub const POSITIONS_VERSION: u32 = 8;
+/// Version from which documents record the parser that indexed them.
+pub const DOCUMENT_FORMATS_VERSION: u32 = 9;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum IndexFormatError {
+    #[error("Not a trustami index file")]
+    InvalidMagic,
+    #[error(
+        "Index was built by an incompatible version of trustami (format version {found}, expected {}), run `trustami migrate` to upgrade it",
+        INDEX_FORMAT_VERSION
+    )]
+    IncompatibleVersion { found: u32 },
+    #[error(
+        "Index was built by a newer version of trustami (format version {found}, expected {}), upgrade trustami or create the index again",
+        INDEX_FORMAT_VERSION
+    )]
+    NewerVersion { found: u32 },
+    #[error("Index file is truncated")]
+    Truncated,
+    #[error("Index file is corrupted: {0}")]
+    Corrupted(&'static str),
+    #[error("Document path {} is not valid unicode", .0.display())]
+    NonUnicodePath(PathBuf),
+}
+
+impl IndexFormatError {
+    /// Error for a file of another format version, only older ones can be migrated.
+    pub fn for_version(found: u32) -> Self {
+        if found > INDEX_FORMAT_VERSION {
+            Self::NewerVersion { found }
+        } else {
+            Self::IncompatibleVersion { found }
+        }
+    }
+}
+
+/// Binary layout, all integers little endian:
+///
+/// - header: magic, format version (u32), document count (u32), term count (u32),
+///   average document length (f32), then the offsets of the three sections (u64 each)
+/// - documents: one u64 offset per document relative to the section, then per document
+///   its path, optional title, length, fingerprint and optional parser name
+/// - dictionary: one u64 offset per term relative to the section, then the terms in
+///   sorted order, each with its document frequency and the position and byte length of
+///   its posting list within the postings section
+/// - postings: per posting the gap to the previous document id, the term frequency, the
+///   number of positions and the gaps between consecutive positions
+///
+/// Unsigned integers are LEB128 varints and strings are prefixed by their byte length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+    version: u32,
+    document_count: u32,
+    term_count: u32,
+    average_document_length: f32,
+    documents_offset: usize,
+    dictionary_offset: usize,
+    postings_offset: usize,
+}
+
+pub fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+fn write_optional_string(buffer: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        // zero marks a missing string, so lengths are shifted by one
+        Some(value) => {
+            write_varint(buffer, value.len() as u64 + 1);
+            buffer.extend_from_slice(value.as_bytes());
+        }
+        None => write_varint(buffer, 0),
+    }
+}
+
+/// Writes records preceded by a table with the offset of each record.
+fn with_offset_table(offsets: &[usize], records: Vec<u8>) -> Vec<u8> {
+    let table_length = offsets.len() * OFFSET_LENGTH;
+    let mut section = Vec::with_capacity(table_length + records.len());
+    for offset in offsets {
+        section.extend_from_slice(&((table_length + offset) as u64).to_le_bytes());
+    }
+    section.extend(records);
+    section
+}
+
+fn encode_documents(documents: &[Document]) -> Result<Vec<u8>, IndexFormatError> {
+    let mut offsets = Vec::with_capacity(documents.len());
+    let mut records = Vec::new();
+    for document in documents {
+        offsets.push(records.len());
+        let path = document
+            .path
+            .to_str()
+            .ok_or_else(|| IndexFormatError::NonUnicodePath(document.path.clone()))?;
+        write_string(&mut records, path);
+        write_optional_string(&mut records, document.title.as_deref());
+        write_varint(&mut records, document.length as u64);
+        write_varint(&mut records, document.fingerprint.modified);
+        write_varint(&mut records, document.fingerprint.size);
+        records.extend_from_slice(&document.fingerprint.content_hash.to_le_bytes());
+        write_optional_string(&mut records, document.format.as_deref());
+    }
+    Ok(with_offset_table(&offsets, records))
+}
+
+/// Encodes the dictionary and postings sections.
+fn encode_terms(inverted_index: &InvertedIndex) -> (Vec<u8>, Vec<u8>) {
+    // the dictionary is already in the sorted order the file layout requires
+    let terms = inverted_index.get_inner_map();
+
+    let mut offsets = Vec::with_capacity(terms.len());
+    let mut records = Vec::new();
+    let mut postings_section = Vec::new();
+    for (term, postings) in terms {
+        let postings_start = postings_section.len();
+        let mut previous_id = 0;
+        for posting in postings {
+            write_varint(
+                &mut postings_section,
+                (posting.document_id - previous_id) as u64,
+            );
+            write_varint(&mut postings_section, posting.term_freq as u64);
+            write_varint(&mut postings_section, posting.positions.len() as u64);
+            let mut previous_position = 0;
+            for &position in &posting.positions {
+                write_varint(&mut postings_section, (position - previous_position) as u64);
+                previous_position = position;
+            }
+            previous_id = posting.document_id;
+        }
+
+        offsets.push(records.len());
+        write_string(&mut records, term);
+        write_varint(&mut records, postings.len() as u64);
+        write_varint(&mut records, postings_start as u64);
+        write_varint(
+            &mut records,
+            (postings_section.len() - postings_start) as u64,
+        );
+    }
+    (with_offset_table(&offsets, records), postings_section)
+}
+
+/// Serializes an index into the binary format.
+pub fn encode_index(index: &Index) -> Result<Vec<u8>, IndexFormatError> {
+    let documents = encode_documents(&index.documents)?;
+    let (dictionary, postings) = encode_terms(&index.inverted_index);
+
+    let documents_offset = HEADER_LENGTH;
+    let dictionary_offset = documents_offset + documents.len();
+    let postings_offset = dictionary_offset + dictionary.len();
+
+    let mut bytes = Vec::with_capacity(postings_offset + postings.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&INDEX_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(index.document_count() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(index.term_count() as u32).to_le_bytes());
+    bytes.extend_from_slice(&index.average_document_length.to_le_bytes());
+    for offset in [documents_offset, dictionary_offset, postings_offset] {
+        bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+    }
+    bytes.extend(documents);
+    bytes.extend(dictionary);
+    bytes.extend(postings);
+    Ok(bytes)
+}
+
+/// Reads values sequentially from a byte slice.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        Self { bytes, position }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], IndexFormatError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(IndexFormatError::Truncated)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], IndexFormatError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, IndexFormatError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, IndexFormatError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, IndexFormatError> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, IndexFormatError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_array::<1>()?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(IndexFormatError::Corrupted("varint is too long"))
+    }
+
+    fn read_u32_varint(&mut self) -> Result<u32, IndexFormatError> {
+        u32::try_from(self.read_varint()?)
+            .map_err(|_| IndexFormatError::Corrupted("value does not fit 32 bits"))
+    }
+
+    fn read_str(&mut self, length: usize) -> Result<&'a str, IndexFormatError> {
+        std::str::from_utf8(self.read_bytes(length)?)
+            .map_err(|_| IndexFormatError::Corrupted("string is not valid utf-8"))
+    }
+
+    fn read_string(&mut self) -> Result<&'a str, IndexFormatError> {
+        let length = self.read_varint()? as usize;
+        self.read_str(length)
+    }
+
+    fn read_optional_string(&mut self) -> Result<Option<String>, IndexFormatError> {
+        Ok(match self.read_varint()? as usize {
+            0 => None,
+            length => Some(self.read_str(length - 1)?.to_string()),
+        })
+    }
+}
+
+fn read_document(cursor: &mut Cursor, version: u32) -> Result<Document, IndexFormatError> {
+    let path = PathBuf::from(cursor.read_string()?);
+    let title = cursor.read_optional_string()?;
+    let length = cursor.read_u32_varint()?;
+    let fingerprint = FileFingerprint {
+        modified: cursor.read_varint()?,
+        size: cursor.read_varint()?,
+        content_hash: cursor.read_u64()?,
+    };
+    let format = if version >= DOCUMENT_FORMATS_VERSION {
+        cursor.read_optional_string()?
+    } else {
+        None
+    };
+    Ok(Document {
+        path,
+        title,
+        length,
+        fingerprint,
+        format,
+    })
+}
+
+fn read_positions(cursor: &mut Cursor) -> Result<Vec<u32>, IndexFormatError> {
+    let count = cursor.read_varint()? as usize;
+    // every position takes at least a byte, which bounds the allocation
+    let mut positions = Vec::with_capacity(count.min(cursor.bytes.len()));
+    let mut position: u32 = 0;
+    for _ in 0..count {
+        position =
+            position
+                .checked_add(cursor.read_u32_varint()?)
+                .ok_or(IndexFormatError::Corrupted(
+                    "token position does not fit 32 bits",
+                ))?;
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+fn read_postings(
+    bytes: &[u8],
+    document_frequency: usize,
+    document_count: u32,
+    has_positions: bool,
+) -> Result<Vec<Posting>, IndexFormatError> {
+    let mut cursor = Cursor::new(bytes, 0);
+    // every posting takes at least a byte, which bounds the allocation
+    let mut postings = Vec::with_capacity(document_frequency.min(bytes.len()));
+    let mut document_id: DocumentId = 0;
+    for _ in 0..document_frequency {
+        document_id = document_id
+            .checked_add(cursor.read_u32_varint()?)
+            .filter(|document_id| *document_id < document_count)
+            .ok_or(IndexFormatError::Corrupted(
+                "posting references a missing document",
+            ))?;
+        let term_freq = cursor.read_u32_varint()?;
+        let positions = if has_positions {
+            read_positions(&mut cursor)?
+        } else {
+            Vec::new()
+        };
+        postings.push(Posting {
+            document_id,
+            term_freq,
+            positions,
+        });
+    }
+    Ok(postings)
+}
+
+/// Format version of a binary index file, read from its header.
+pub fn read_format_version(bytes: &[u8]) -> Result<u32, IndexFormatError> {
+    let mut cursor = Cursor::new(bytes, 0);
+    if cursor.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(IndexFormatError::InvalidMagic);
+    }
+    cursor.read_u32()
+}
+
+struct DictionaryEntry<'a> {
+    term: &'a str,
+    document_frequency: usize,
+    postings_start: usize,
+    postings_length: usize,
+}
+
+fn read_dictionary_entry<'a>(
+    cursor: &mut Cursor<'a>,
+) -> Result<DictionaryEntry<'a>, IndexFormatError> {
+    Ok(DictionaryEntry {
+        term: cursor.read_string()?,
+        document_frequency: cursor.read_varint()? as usize,
+        postings_start: cursor.read_varint()? as usize,
+        postings_length: cursor.read_varint()? as usize,
+    })
+}
+
+/// A binary index read in place, only the parts that are accessed get decoded.
+pub struct IndexFile<B> {
+    bytes: B,
+    header: Header,
+}
+
+/// An index file mapped into memory, pages are only loaded once they are read.
+pub type MappedIndex = IndexFile<Mmap>;
+
+impl MappedIndex {
+    pub fn open<P>(index_file_path: P) -> Result<Self, anyhow::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let index_file_path = index_file_path.as_ref();
+        let file = File::open(index_file_path).with_context(|| {
+            format!("Failed to open index file at {}", index_file_path.display())
+        })?;
+        // SAFETY: the mapping is only read, and trustami replaces index files by renaming
+        // a new file over them, so a mapped file is never modified by another trustami process
+        let mmap = unsafe { Mmap::map(&file) }.with_context(|| {
+            format!("Failed to map index file at {}", index_file_path.display())
+        })?;
+        IndexFile::new(mmap)
+            .with_context(|| format!("Failed to load index file at {}", index_file_path.display()))
+    }
+}
+
+impl<B> IndexFile<B>
+where
+    B: AsRef<[u8]>,
+{
+    /// Checks the header, the rest of the file is validated as it is read.
+    pub fn new(bytes: B) -> Result<Self, IndexFormatError> {
+        let index_file = Self::with_any_version(bytes)?;
+        if index_file.header.version != INDEX_FORMAT_VERSION {
+            return Err(IndexFormatError::for_version(index_file.header.version));
+        }
+        Ok(index_file)
+    }
+
+    /// Like `new`, but also accepts the earlier binary format versions.
+    fn with_any_version(bytes: B) -> Result<Self, IndexFormatError> {
+        let data = bytes.as_ref();
+        let version = read_format_version(data)?;
+        if !(FIRST_BINARY_VERSION..=INDEX_FORMAT_VERSION).contains(&version) {
+            return Err(IndexFormatError::for_version(version));
+        }
+        let mut cursor = Cursor::new(data, MAGIC.len() + size_of::<u32>());
+        let document_count = cursor.read_u32()?;
+        let term_count = cursor.read_u32()?;
+        let average_document_length = cursor.read_f32()?;
+        let mut section_start = || -> Result<usize, IndexFormatError> {
+            usize::try_from(cursor.read_u64()?)
+                .ok()
+                .filter(|offset| *offset <= data.len())
+                .ok_or(IndexFormatError::Truncated)
+        };
+        let header = Header {
+            version,
+            document_count,
+            term_count,
+            average_document_length,
+            documents_offset: section_start()?,
+            dictionary_offset: section_start()?,
+            postings_offset: section_start()?,
+        };
+
+        if header.documents_offset < HEADER_LENGTH
+            || header.dictionary_offset < header.documents_offset
+            || header.postings_offset < header.dictionary_offset
+        {
+            return Err(IndexFormatError::Corrupted("sections are out of order"));
+        }
+        // the counts decide how much is read and allocated, so they must fit their sections
+        let fits_offset_table = |count: u32, section_length: usize| {
+            (count as usize)
+                .checked_mul(OFFSET_LENGTH)
+                .is_some_and(|table_length| table_length <= section_length)
+        };
+        if !fits_offset_table(
+            header.document_count,
+            header.dictionary_offset - header.documents_offset,
+        ) || !fits_offset_table(
+            header.term_count,
+            header.postings_offset - header.dictionary_offset,
+        ) {
+            return Err(IndexFormatError::Truncated);
+        }
+        Ok(Self { bytes, header })
+    }
+
+    fn data(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Cursor positioned on a record of a section with an offset table.
+    fn record(
+        &self,
+        section_offset: usize,
+        position: usize,
+    ) -> Result<Cursor<'_>, IndexFormatError> {
+        let mut table = Cursor::new(self.data(), section_offset + position * OFFSET_LENGTH);
+        let record_start = usize::try_from(table.read_u64()?)
+            .ok()
+            .and_then(|record_offset| section_offset.checked_add(record_offset))
+            .filter(|record_start| *record_start <= self.data().len())
+            .ok_or(IndexFormatError::Truncated)?;
+        Ok(Cursor::new(self.data(), record_start))
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.header.document_count as usize
+    }
+
+    pub fn term_count(&self) -> usize {
+        self.header.term_count as usize
+    }
+
+    pub fn average_document_length(&self) -> f32 {
+        self.header.average_document_length
+    }
+
+    pub fn document(&self, document_id: DocumentId) -> Result<Document, IndexFormatError> {
+        if document_id >= self.header.document_count {
+            return Err(IndexFormatError::Corrupted(
+                "posting references a missing document",
+            ));
+        }
+        read_document(
+            &mut self.record(self.header.documents_offset, document_id as usize)?,
+            self.header.version,
+        )
+    }
+
+    /// Binary search for the position of the first term not sorting before `term`.
+    fn lower_bound(&self, term: &str) -> Result<usize, IndexFormatError> {
+        let (mut low, mut high) = (0, self.term_count());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry =
+                read_dictionary_entry(&mut self.record(self.header.dictionary_offset, middle)?)?;
+            match entry.term.cmp(term) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater | Ordering::Equal => high = middle,
+            }
+        }
+        Ok(low)
+    }
+
+    fn find_term(&self, term: &str) -> Result<Option<DictionaryEntry<'_>>, IndexFormatError> {
+        let position = self.lower_bound(term)?;
+        if position == self.term_count() {
+            return Ok(None);
+        }
+        let entry =
+            read_dictionary_entry(&mut self.record(self.header.dictionary_offset, position)?)?;
+        Ok((entry.term == term).then_some(entry))
+    }
+
+    /// Calls `visit` on the terms starting with `prefix` in sorted order, until it returns
+    /// `false`. Only the dictionary entries of these terms are read.
+    pub fn visit_terms<F>(&self, prefix: &str, mut visit: F) -> Result<(), IndexFormatError>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let first = self.lower_bound(prefix)?;
+        if first == self.term_count() {
+            return Ok(());
+        }
+        // entries follow each other in sorted order
+        let mut cursor = self.record(self.header.dictionary_offset, first)?;
+        for _ in first..self.term_count() {
+            let entry = read_dictionary_entry(&mut cursor)?;
+            if !entry.term.starts_with(prefix) || !visit(entry.term) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_entry_postings(
+        &self,
+        entry: &DictionaryEntry,
+    ) -> Result<Vec<Posting>, IndexFormatError> {
+        let postings_section = &self.data()[self.header.postings_offset..];
+        let bytes = Cursor::new(postings_section, entry.postings_start)
+            .read_bytes(entry.postings_length)?;
+        read_postings(
+            bytes,
+            entry.document_frequency,
+            self.header.document_count,
+            self.header.version >= POSITIONS_VERSION,
+        )
+    }
+
+    /// Number of documents containing `term`.
+    pub fn document_frequency(&self, term: &str) -> Result<usize, IndexFormatError> {
+        Ok(self
+            .find_term(term)?
+            .map_or(0, |entry| entry.document_frequency))
+    }
+
+    pub fn postings(&self, term: &str) -> Result<Vec<Posting>, IndexFormatError> {
+        match self.find_term(term)? {
+            Some(entry) => self.read_entry_postings(&entry),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Decodes the whole index into memory.
+    pub fn to_index(&self) -> Result<Index, IndexFormatError> {
+        // records follow their offset tables, so both sections can be read sequentially
+        let mut cursor = Cursor::new(
+            self.data(),
+            self.header.documents_offset + self.document_count() * OFFSET_LENGTH,
+        );
+        let documents = (0..self.header.document_count)
+            .map(|_| read_document(&mut cursor, self.header.version))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut cursor = Cursor::new(
+            self.data(),
+            self.header.dictionary_offset + self.term_count() * OFFSET_LENGTH,
+        );
+        let mut terms = BTreeMap::new();
+        for _ in 0..self.header.term_count {
+            let entry = read_dictionary_entry(&mut cursor)?;
+            terms.insert(entry.term.to_string(), self.read_entry_postings(&entry)?);
+        }
+
+        let inverted_index = InvertedIndex::from(terms);
+        let inverse_document_frequency =
+            InverseDocumentFrequency::from_inverted_index(&inverted_index, documents.len());
+        Ok(Index {
+            documents,
+            inverted_index,
+            inverse_document_frequency,
+            average_document_length: self.header.average_document_length,
+        })
+    }
+}
+
+impl<B> IndexReader for IndexFile<B>
+where
+    B: AsRef<[u8]>,
+{
+    fn document_count(&self) -> usize {
+        self.document_count()
+    }
+
+    fn average_document_length(&self) -> f32 {
+        self.average_document_length()
+    }
+
+    fn inverse_document_frequency(&self, term: &str) -> Result<f32, anyhow::Error> {
+        Ok(smoothed_idf(
+            self.document_count(),
+            self.document_frequency(term)?,
+        ))
+    }
+
+    fn postings(&self, term: &str) -> Result<Cow<'_, [Posting]>, anyhow::Error> {
+        Ok(Cow::Owned(self.postings(term)?))
+    }
+
+    fn visit_terms(
+        &self,
+        prefix: &str,
+        visit: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), anyhow::Error> {
+        Ok(self.visit_terms(prefix, visit)?)
+    }
+
+    fn document(&self, document_id: DocumentId) -> Result<Cow<'_, Document>, anyhow::Error> {
+        Ok(Cow::Owned(self.document(document_id)?))
+    }
+}
+
+/// Deserializes a whole index from the binary format.
+pub fn decode_index(bytes: &[u8]) -> Result<Index, IndexFormatError> {
+    IndexFile::new(bytes)?.to_index()
+}
+
+/// Deserializes an index written in the current or an earlier binary format version.
+/// Postings of versions without token positions have none.
+pub fn decode_index_of_any_version(bytes: &[u8]) -> Result<Index, IndexFormatError> {
+    IndexFile::with_any_version(bytes)?.to_index()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::fingerprint::FileFingerprint;
+    use std::fs;
+    use tempfile::tempdir;
+
+    use crate::index_format::{
+        Cursor, IndexFile, IndexFormatError, MappedIndex, decode_index, encode_index,
+        read_postings, write_varint,
+    };
+    use crate::query::Query;
+    use crate::ranking::Ranking;
+    use crate::term_frequency::TermFrequency;
+    use crate::utils::{INDEX_FORMAT_VERSION, Index};
+
+    fn create_index() -> Index {
+        let mut first = TermFrequency::new(PathBuf::from("/docs/rome.md"));
+        for token in ["rome", "empire", "rome"] {
+            first.update(token);
+        }
+        first.title = Some(String::from("Rome"));
+        first.format = Some(String::from("markdown"));
+        first.fingerprint = FileFingerprint {
+            modified: 1_700_000_000_000_000_000,
+            size: 42,
+            content_hash: 7,
+        };
+        let mut second = TermFrequency::new(PathBuf::from("/docs/paris.txt"));
+        second.update("paris");
+        let mut third = TermFrequency::new(PathBuf::from("/docs/empire.txt"));
+        third.update("empire");
+        Index::from_term_frequencies(vec![first, second, third])
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buffer = Vec::new();
+            write_varint(&mut buffer, value);
+
+            assert_eq!(Cursor::new(&buffer, 0).read_varint(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn index_round_trips() {
+        let index = create_index();
+
+        let decoded = decode_index(&encode_index(&index).unwrap()).unwrap();
+
+        assert_eq!(decoded.documents, index.documents);
+        assert_eq!(
+            decoded.inverted_index.get_inner_map(),
+            index.inverted_index.get_inner_map()
+        );
+        assert_eq!(
+            decoded.inverse_document_frequency.get_inner_map(),
+            index.inverse_document_frequency.get_inner_map()
+        );
+        assert_eq!(
+            decoded.average_document_length,
+            index.average_document_length
+        );
+    }
+
+    #[test]
+    fn binary_index_is_smaller_than_json() {
+        let index = create_index();
+
+        let binary = encode_index(&index).unwrap();
+        let json = serde_json::to_vec(&index).unwrap();
+
+        assert!(binary.len() < json.len());
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let bytes = encode_index(&create_index()).unwrap();
+
+        assert_eq!(
+            decode_index(b"{\"documents\":[]}").unwrap_err(),
+            IndexFormatError::InvalidMagic
+        );
+        assert_eq!(
+            decode_index(&bytes[..bytes.len() - 1]).unwrap_err(),
+            IndexFormatError::Truncated
+        );
+        let mut other_version = bytes.clone();
+        other_version[8..12].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            decode_index(&other_version).unwrap_err(),
+            IndexFormatError::IncompatibleVersion { found: 0 }
+        );
+        other_version[8..12].copy_from_slice(&(INDEX_FORMAT_VERSION + 1).to_le_bytes());
+        let err = decode_index(&other_version).unwrap_err();
+        assert_eq!(
+            err,
+            IndexFormatError::NewerVersion {
+                found: INDEX_FORMAT_VERSION + 1
+            }
+        );
+        assert!(!err.to_string().contains("migrate"));
+    }
+
+    #[test]
+    fn corrupted_header_is_an_error() {
+        let bytes = encode_index(&create_index()).unwrap();
+
+        let mut term_count = bytes.clone();
+        term_count[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            decode_index(&term_count).unwrap_err(),
+            IndexFormatError::Truncated
+        );
+        let mut document_count = bytes.clone();
+        document_count[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            decode_index(&document_count).unwrap_err(),
+            IndexFormatError::Truncated
+        );
+        let mut section_order = bytes;
+        section_order[32..40].copy_from_slice(&0u64.to_le_bytes());
+        assert_eq!(
+            decode_index(&section_order).unwrap_err(),
+            IndexFormatError::Corrupted("sections are out of order")
+        );
+    }
+
+    #[test]
+    fn corrupted_record_offsets_are_an_error() {
+        let mut bytes = encode_index(&create_index()).unwrap();
+        let section_offset = |bytes: &[u8], at: usize| {
+            u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize
+        };
+        let documents_offset = section_offset(&bytes, 24);
+        let dictionary_offset = section_offset(&bytes, 32);
+        for table in [documents_offset, dictionary_offset] {
+            bytes[table..table + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        }
+        let index_file = IndexFile::new(bytes).unwrap();
+
+        assert_eq!(
+            index_file.document(0).unwrap_err(),
+            IndexFormatError::Truncated
+        );
+        assert_eq!(
+            index_file.postings("empire").unwrap_err(),
+            IndexFormatError::Truncated
+        );
+    }
+
+    #[test]
+    fn corrupted_document_frequency_is_an_error() {
+        assert_eq!(
+            read_postings(&[0, 1], usize::MAX, 1, false),
+            Err(IndexFormatError::Truncated)
+        );
+    }
+
+    #[test]
+    fn terms_are_looked_up_in_place() {
+        let index = create_index();
+        let index_file = IndexFile::new(encode_index(&index).unwrap()).unwrap();
+
+        for term in ["empire", "paris", "rome", "missing", ""] {
+            assert_eq!(
+                index_file.postings(term).unwrap(),
+                index.inverted_index.postings(term)
+            );
+        }
+        assert_eq!(index_file.document_frequency("empire").unwrap(), 2);
+        let mut terms = Vec::new();
+        index_file
+            .visit_terms("", |term| {
+                terms.push(term.to_string());
+                true
+            })
+            .unwrap();
+        assert_eq!(terms, ["empire", "paris", "rome"]);
+        terms.clear();
+        index_file
+            .visit_terms("p", |term| {
+                terms.push(term.to_string());
+                true
+            })
+            .unwrap();
+        assert_eq!(terms, ["paris"]);
+        assert_eq!(index_file.document(2).unwrap(), index.documents[2]);
+        assert!(index_file.document(3).is_err());
+    }
+
+    #[test]
+    fn mapped_index_ranks_like_loaded_index() {
+        let index = create_index();
+        let temp = tempdir().unwrap();
+        let index_file_path = temp.path().join("index.bin");
+        fs::write(&index_file_path, encode_index(&index).unwrap()).unwrap();
+
+        let mapped = MappedIndex::open(&index_file_path).unwrap();
+        let query = Query::parse(r#"empire "rome empire""#).unwrap();
+        let mut expected = Ranking::bm25().search(&index, &query).unwrap();
+        let mut results = Ranking::bm25().search(&mapped, &query).unwrap();
+        expected.sort_by(|a, b| a.document_path.cmp(&b.document_path));
+        results.sort_by(|a, b| a.document_path.cmp(&b.document_path));
+
+        assert_eq!(results.len(), 2);
+        for (result, expected) in results.iter().zip(&expected) {
+            assert_eq!(result.document_path, expected.document_path);
+            assert_eq!(result.title, expected.title);
+            assert!((result.score - expected.score).abs() < 1e-6);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::inverted_index::InvertedIndex;
 use crate::term_frequency::TermFrequency;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -5,31 +6,117 @@ use std::collections::HashMap;
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct InverseDocumentFrequency(HashMap<String, f32>);
 
+/// Smoothed IDF: one is added to both counts so unseen terms never divide by zero.
+pub fn smoothed_idf(docs_count: usize, matched_docs_count: usize) -> f32 {
+    ((docs_count + 1) as f32 / (matched_docs_count + 1) as f32).log10()
+}
+
 impl InverseDocumentFrequency {
     pub fn new() -> Self {
         Self(HashMap::new())
     }
 
+    /// Builds the IDF table from a single pass over the documents.
+    pub fn from_documents(docs: &[TermFrequency]) -> Self {
+        let mut document_frequencies: HashMap<&str, usize> = HashMap::new();
+        for doc in docs {
+            for term in doc.term_freq.keys() {
+                *document_frequencies.entry(term.as_str()).or_default() += 1;
+            }
+        }
+
+        let idf = document_frequencies
+            .into_iter()
+            .map(|(term, matched_docs_count)| {
+                (
+                    term.to_string(),
+                    smoothed_idf(docs.len(), matched_docs_count),
+                )
+            })
+            .collect();
+        Self(idf)
+    }
+
+    /// Builds the IDF table from the length of every posting list.
+    pub fn from_inverted_index(inverted_index: &InvertedIndex, docs_count: usize) -> Self {
+        let idf = inverted_index
+            .get_inner_map()
+            .iter()
+            .map(|(term, postings)| (term.clone(), smoothed_idf(docs_count, postings.len())))
+            .collect();
+        Self(idf)
+    }
+
     pub fn get_inner_map(&self) -> &HashMap<String, f32> {
         &self.0
     }
 
-    pub fn update(&mut self, term: &str, docs: &Vec<TermFrequency>) {
-        if !self.0.contains_key(term) {
-            // one is added for smoothing
-            let docs_count = docs.len() + 1;
+    /// IDF weight of a term, falling back to the smoothed value for terms missing from the corpus.
+    pub fn weight(&self, term: &str, docs_count: usize) -> f32 {
+        self.0
+            .get(term)
+            .copied()
+            .unwrap_or_else(|| smoothed_idf(docs_count, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
 
-            let mut matched_docs_count = 0;
-            for doc in docs {
-                if doc.term_freq.contains_key(term) {
-                    matched_docs_count += 1;
-                }
-            }
-            // one is added for smoothing
-            matched_docs_count += 1;
+    use crate::inverse_doc_frequency::{InverseDocumentFrequency, smoothed_idf};
+    use crate::term_frequency::TermFrequency;
 
-            let idf_value = (docs_count / matched_docs_count) as f32;
-            let _ = self.0.insert(term.to_string(), idf_value);
+    fn create_doc(name: &str, tokens: &[&str]) -> TermFrequency {
+        let mut doc = TermFrequency::new(PathBuf::from(name));
+        for token in tokens {
+            doc.update(token);
         }
+        doc
+    }
+
+    #[test]
+    fn idf_uses_floating_point_division() {
+        let docs = vec![
+            create_doc("a.xml", &["rome", "italy"]),
+            create_doc("b.xml", &["rome"]),
+            create_doc("c.xml", &["paris"]),
+        ];
+
+        let idf = InverseDocumentFrequency::from_documents(&docs);
+
+        // (3 + 1) / (2 + 1) would be truncated to 1 with integer division
+        let expected = (4.0_f32 / 3.0).log10();
+        assert!((idf.weight("rome", docs.len()) - expected).abs() < f32::EPSILON);
+        assert!(idf.weight("rome", docs.len()) > 0.0);
+    }
+
+    #[test]
+    fn rarer_terms_weigh_more() {
+        let docs = vec![
+            create_doc("a.xml", &["rome", "italy"]),
+            create_doc("b.xml", &["rome"]),
+            create_doc("c.xml", &["paris"]),
+        ];
+
+        let idf = InverseDocumentFrequency::from_documents(&docs);
+
+        assert!(idf.weight("italy", docs.len()) > idf.weight("rome", docs.len()));
+    }
+
+    #[test]
+    fn unseen_terms_use_the_same_formula() {
+        let docs = vec![
+            create_doc("a.xml", &["rome"]),
+            create_doc("b.xml", &["rome"]),
+        ];
+
+        let idf = InverseDocumentFrequency::from_documents(&docs);
+
+        assert_eq!(
+            idf.weight("missing", docs.len()),
+            smoothed_idf(docs.len(), 0)
+        );
+        assert!(idf.weight("missing", docs.len()) > idf.weight("rome", docs.len()));
     }
 }
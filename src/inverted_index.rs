@@ -0,0 +1,188 @@
+use crate::fingerprint::FileFingerprint;
+use crate::term_frequency::TermFrequency;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::PathBuf;
+
+pub type DocumentId = u32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Document {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    /// Number of tokens in the document
+    pub length: u32,
+    #[serde(default)]
+    pub fingerprint: FileFingerprint,
+    /// Name of the parser that indexed the document, unknown for older indexes
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub document_id: DocumentId,
+    pub term_freq: u32,
+    /// Token positions of the term in the document, empty for documents indexed before
+    /// positions were recorded
+    #[serde(default)]
+    pub positions: Vec<u32>,
+}
+
+/// Maps every term, in sorted order, to the documents it occurs in, sorted by document id.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct InvertedIndex(BTreeMap<String, Vec<Posting>>);
+
+impl InvertedIndex {
+    pub fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    pub fn get_inner_map(&self) -> &BTreeMap<String, Vec<Posting>> {
+        &self.0
+    }
+
+    /// Terms starting with `prefix`, in sorted order.
+    pub fn terms_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a String> {
+        self.0
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(term, _)| term)
+            .take_while(move |term| term.starts_with(prefix))
+    }
+
+    pub fn postings(&self, term: &str) -> &[Posting] {
+        self.0.get(term).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Drops the postings of removed documents and renumbers the remaining ones. `new_id`
+    /// maps an old document id to its new id, or `None` when the document was removed, and
+    /// must preserve the relative order of the ids.
+    pub fn remap_documents<F>(&mut self, new_id: F)
+    where
+        F: Fn(DocumentId) -> Option<DocumentId>,
+    {
+        self.0.retain(|_, postings| {
+            postings.retain_mut(|posting| match new_id(posting.document_id) {
+                Some(document_id) => {
+                    posting.document_id = document_id;
+                    true
+                }
+                None => false,
+            });
+            !postings.is_empty()
+        });
+    }
+
+    /// Appends the postings of a document, which must have a higher id than any added before.
+    pub fn add_document(&mut self, document_id: DocumentId, doc: &TermFrequency) {
+        for (term, &term_freq) in &doc.term_freq {
+            self.0.entry(term.clone()).or_default().push(Posting {
+                document_id,
+                term_freq,
+                positions: doc.positions.get(term).cloned().unwrap_or_default(),
+            });
+        }
+    }
+}
+
+impl From<BTreeMap<String, Vec<Posting>>> for InvertedIndex {
+    fn from(postings: BTreeMap<String, Vec<Posting>>) -> Self {
+        Self(postings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::inverted_index::{InvertedIndex, Posting};
+    use crate::term_frequency::TermFrequency;
+
+    #[test]
+    fn postings_only_reference_matching_documents() {
+        let mut first = TermFrequency::new(PathBuf::from("a.xml"));
+        for token in ["rome", "rome", "italy"] {
+            first.update(token);
+        }
+        let mut second = TermFrequency::new(PathBuf::from("b.xml"));
+        second.update("paris");
+        let mut third = TermFrequency::new(PathBuf::from("c.xml"));
+        third.update("rome");
+
+        let mut inverted_index = InvertedIndex::new();
+        for (document_id, doc) in [first, second, third].iter().enumerate() {
+            inverted_index.add_document(document_id as u32, doc);
+        }
+
+        assert_eq!(
+            inverted_index.postings("rome"),
+            [
+                Posting {
+                    document_id: 0,
+                    term_freq: 2,
+                    positions: vec![0, 1]
+                },
+                Posting {
+                    document_id: 2,
+                    term_freq: 1,
+                    positions: vec![0]
+                }
+            ]
+        );
+        assert!(inverted_index.postings("missing").is_empty());
+    }
+
+    #[test]
+    fn terms_are_scanned_by_prefix_in_order() {
+        let mut doc = TermFrequency::new(PathBuf::from("a.xml"));
+        for token in ["romulus", "rome", "paris", "roman", "ro", "rp"] {
+            doc.update(token);
+        }
+        let mut inverted_index = InvertedIndex::new();
+        inverted_index.add_document(0, &doc);
+
+        let terms: Vec<&String> = inverted_index.terms_with_prefix("rom").collect();
+
+        assert_eq!(terms, ["roman", "rome", "romulus"]);
+        assert_eq!(inverted_index.terms_with_prefix("").count(), 6);
+        assert_eq!(inverted_index.terms_with_prefix("x").count(), 0);
+    }
+
+    #[test]
+    fn removed_documents_are_remapped() {
+        let mut first = TermFrequency::new(PathBuf::from("a.xml"));
+        first.update("rome");
+        let mut second = TermFrequency::new(PathBuf::from("b.xml"));
+        second.update("paris");
+        let mut third = TermFrequency::new(PathBuf::from("c.xml"));
+        third.update("rome");
+
+        let mut inverted_index = InvertedIndex::new();
+        for (document_id, doc) in [first, second, third].iter().enumerate() {
+            inverted_index.add_document(document_id as u32, doc);
+        }
+        inverted_index.remap_documents(|document_id| match document_id {
+            0 => Some(0),
+            1 => None,
+            _ => Some(document_id - 1),
+        });
+
+        assert_eq!(
+            inverted_index.postings("rome"),
+            [
+                Posting {
+                    document_id: 0,
+                    term_freq: 1,
+                    positions: vec![0]
+                },
+                Posting {
+                    document_id: 1,
+                    term_freq: 1,
+                    positions: vec![0]
+                }
+            ]
+        );
+        assert!(!inverted_index.get_inner_map().contains_key("paris"));
+    }
+}
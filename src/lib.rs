@@ -1,8 +1,19 @@
+pub mod fingerprint;
+pub mod gitignore;
+pub mod glob;
+pub mod index_format;
 pub mod inverse_doc_frequency;
+pub mod inverted_index;
+pub mod migration;
 pub mod os_interaction;
+pub mod parser_registry;
 pub mod parsers;
 pub mod path_resolver;
+pub mod query;
+pub mod ranking;
+pub mod statistics;
 pub mod term_frequency;
 pub mod tokenizer;
 pub mod utils;
 pub mod view;
+pub mod watcher;
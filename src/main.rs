@@ -1,12 +1,21 @@
 use anyhow::{self, Context};
-use clap::{Parser, Subcommand};
-use std::fs::File;
-use std::io::{Read, Write};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fs::{self, File};
+use std::io::{BufWriter, IsTerminal, Write};
 use std::path::PathBuf;
-use trustami::os_interaction;
-use trustami::path_resolver;
-use trustami::utils::{self, Index, TfIdf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use trustami::glob::Glob;
+use trustami::index_format::{self, MappedIndex};
+use trustami::os_interaction::{self, OverwritePolicy, RefusedError};
+use trustami::parser_registry::ParserRegistry;
+use trustami::path_resolver::{self, TraversalOptions};
+use trustami::query::{self, Query};
+use trustami::ranking::{self, Ranking};
+use trustami::statistics::IndexStatistics;
+use trustami::utils;
 use trustami::view;
+use trustami::watcher::{self, DirectoryWatcher};
 
 #[derive(Debug, Parser)]
 #[command(version)]
@@ -17,12 +26,27 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Command {
-    /// Search term in the specified directory
+    /// Search term in the specified index
     Query {
-        //#[arg(help = "One or more terms to search")]
+        #[arg(
+            help = "Terms to search, combined with AND, OR, NOT, parentheses, +required and -excluded. Quoted phrases match consecutive terms, \"a b\"~N allows N other terms in between, * and ? are wildcards and term~N matches terms within N edits"
+        )]
         query_string: String,
-        #[arg(help="Directory to search in", default_value=utils::get_current_directory())]
-        dir_path: PathBuf,
+        #[arg(short, long, help = "Name of the index to search in")]
+        index: String,
+        #[arg(long, value_enum, default_value_t = RankingKind::Tfidf, help = "Ranking function")]
+        ranking: RankingKind,
+        #[arg(long, default_value_t = ranking::DEFAULT_K1, help = "BM25 term frequency saturation")]
+        k1: f32,
+        #[arg(long, default_value_t = ranking::DEFAULT_B, help = "BM25 document length normalization")]
+        b: f32,
+        #[arg(long, default_value_t = query::DEFAULT_MAX_EXPANSIONS, help = "Maximum number of terms a wildcard expands to")]
+        max_expansions: usize,
+        #[arg(
+            long,
+            help = "Do not search similar terms for terms missing from the index"
+        )]
+        no_fuzzy: bool,
     },
     /// Index the documents in the specified directory
     NewIndex {
@@ -30,81 +54,440 @@ enum Command {
         index_name: String,
         #[arg(help="Directory to index", default_value=utils::get_current_directory())]
         dir_path: PathBuf,
+        #[arg(long, value_parser = Glob::new, help = "Only index files matching this glob")]
+        include: Vec<Glob>,
+        #[arg(long, value_parser = Glob::new, help = "Skip files and directories matching this glob")]
+        exclude: Vec<Glob>,
+        #[arg(
+            long,
+            value_parser = parse_max_depth,
+            help = "Maximum depth of indexed files, 1 only indexes the top level"
+        )]
+        max_depth: Option<usize>,
+        #[arg(
+            long,
+            help = "Do not skip files listed in .gitignore, .ignore or .trustamiignore"
+        )]
+        no_ignore: bool,
+        #[arg(
+            long,
+            conflicts_with = "no_clobber",
+            help = "Replace an existing index without asking"
+        )]
+        force: bool,
+        #[arg(
+            long,
+            help = "Never replace an existing index, exiting with status 3 instead"
+        )]
+        no_clobber: bool,
+    },
+    /// Re-index the added and changed documents of an existing index
+    Refresh {
+        #[arg(help = "Name of the index to refresh")]
+        index_name: String,
+    },
+    /// Keep an index up to date while its documents change
+    Watch {
+        #[arg(help = "Name of the index to keep up to date")]
+        index_name: String,
+        #[arg(
+            long,
+            help = "Poll the directory instead of using change notifications"
+        )]
+        poll: bool,
+        #[arg(
+            long,
+            default_value_t = watcher::DEFAULT_DEBOUNCE.as_millis() as u64,
+            help = "Milliseconds without changes before they are applied"
+        )]
+        debounce_ms: u64,
+    },
+    /// Write an index as JSON
+    Export {
+        #[arg(help = "Name of the index to export")]
+        index_name: String,
+        #[arg(short, long, help = "File to write to instead of standard output")]
+        output: Option<PathBuf>,
+    },
+    /// Show statistics about an index
+    Info {
+        #[arg(help = "Name of the index to describe")]
+        index_name: String,
+        #[arg(
+            long,
+            default_value_t = 10,
+            help = "Number of terms to list per ranking"
+        )]
+        top: usize,
+        #[arg(long, help = "Print the statistics as JSON")]
+        json: bool,
+    },
+    /// Upgrade indexes built by an earlier version of trustami
+    Migrate {
+        #[arg(help = "Names of the indexes to upgrade, all indexes when omitted")]
+        index_names: Vec<String>,
+    },
+    /// Delete an index
+    Delete {
+        #[arg(help = "Name of the index to delete")]
+        index_name: String,
+    },
+    /// Give an index another name
+    Rename {
+        #[arg(help = "Name of the index to rename")]
+        index_name: String,
+        #[arg(help = "New name of the index")]
+        new_name: String,
+        #[arg(long, help = "Replace an existing index with the new name")]
+        force: bool,
+    },
+    /// Copy an index under another name
+    Copy {
+        #[arg(help = "Name of the index to copy")]
+        index_name: String,
+        #[arg(help = "Name of the copy")]
+        new_name: String,
+        #[arg(long, help = "Replace an existing index with the new name")]
+        force: bool,
     },
     List,
+    /// List the supported document formats
+    Formats,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum RankingKind {
+    Bm25,
+    Tfidf,
 }
 
-fn main() -> Result<(), anyhow::Error> {
+/// Depths count the levels of indexed files, so zero would index nothing.
+fn parse_max_depth(value: &str) -> Result<usize, String> {
+    let max_depth: usize = value.parse().map_err(|err| format!("{err}"))?;
+    if max_depth == 0 {
+        return Err(String::from(
+            "must be at least 1, which only indexes the top level",
+        ));
+    }
+    Ok(max_depth)
+}
+
+/// Exit status when an existing index would have to be replaced but was not.
+const EXIT_REFUSED: u8 = 3;
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            if err.downcast_ref::<RefusedError>().is_some() {
+                ExitCode::from(EXIT_REFUSED)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), anyhow::Error> {
     let user_data_directory =
         dirs::data_local_dir().context("Couldn't find the user's data directory.")?;
 
     match &cli.command {
         Command::Query {
             query_string,
-            dir_path,
+            index,
+            ranking,
+            k1,
+            b,
+            max_expansions,
+            no_fuzzy,
         } => {
-            // TODO: point to correct index path
-            let index: Index;
-
-            if let Ok(mut index_file_handle) = File::open("index.json") {
-                // index was found, load it
-                let mut buf = String::new();
-                index_file_handle.read_to_string(&mut buf)?;
-                index = serde_json::from_str(&buf)?;
-            } else {
-                // TODO: build index similarly to create index command
-                let file_paths = path_resolver::collect_valid_paths(dir_path)?;
-                index = utils::index_docs(&file_paths);
-            }
+            let index_file_path = os_interaction::get_index_file_path(user_data_directory, index)?;
+            // only the dictionary entries and postings of the query terms are read
+            let index = MappedIndex::open(&index_file_path)?;
 
-            let Index {
-                term_frequencies,
-                inverse_document_frequency,
-            } = index;
-            let mut results: Vec<TfIdf> = Vec::new();
-
-            // COMPUTE TF IDF
-            for tf_doc in &term_frequencies {
-                let tfidf = TfIdf::new(
-                    query_string,
-                    tf_doc,
-                    &inverse_document_frequency,
-                    term_frequencies.len(),
+            let ranking = match ranking {
+                RankingKind::Bm25 => Ranking::Bm25 { k1: *k1, b: *b },
+                RankingKind::Tfidf => Ranking::TfIdf,
+            };
+            let expansion =
+                Query::parse(query_string)?.expand(&index, *max_expansions, !*no_fuzzy)?;
+            for pattern in &expansion.truncated {
+                eprintln!(
+                    "Warning: {pattern} matches more than {max_expansions} terms, only the first {max_expansions} are searched."
                 );
-                results.push(tfidf);
             }
+            for term in &expansion.fuzzy_fallbacks {
+                eprintln!("Note: {term} does not occur in the index, searching similar terms.");
+            }
+            let results = ranking.search(&index, &expansion.query)?;
             view::present_results_cli(results);
             Ok(())
         }
         Command::NewIndex {
             dir_path,
             index_name,
+            include,
+            exclude,
+            max_depth,
+            no_ignore,
+            force,
+            no_clobber,
         } => {
-            let mut input = std::io::stdin().lock();
-            let mut file_handle =
-                os_interaction::create_index_file(user_data_directory, index_name, &mut input)?;
-            let file_paths = path_resolver::collect_valid_paths(dir_path)?;
-            let new_index = utils::index_docs(&file_paths);
-            let serialized = serde_json::to_string(&new_index)
+            let input = std::io::stdin();
+            let overwrite_policy = if *force {
+                OverwritePolicy::Replace
+            } else if *no_clobber {
+                OverwritePolicy::Refuse
+            } else if input.is_terminal() {
+                OverwritePolicy::Prompt
+            } else {
+                OverwritePolicy::NonInteractive
+            };
+            let mut file_handle = os_interaction::create_index_file(
+                user_data_directory.clone(),
+                index_name,
+                overwrite_policy,
+                input.lock(),
+            )?;
+            let build_start = Instant::now();
+            let parser_registry = ParserRegistry::default();
+            let traversal_options = TraversalOptions {
+                include: include.clone(),
+                exclude: exclude.clone(),
+                max_depth: *max_depth,
+                respect_ignore_files: !no_ignore,
+            };
+            // documents are stored with absolute paths so that refreshes find them again
+            let source_directory = dir_path
+                .canonicalize()
+                .context("Failed to find specified directory.")?;
+            let file_paths = path_resolver::collect_valid_paths(
+                &source_directory,
+                &parser_registry,
+                &traversal_options,
+            )?;
+            let new_index = utils::index_docs(&file_paths, &parser_registry);
+            let encoded = index_format::encode_index(&new_index)
                 .context("Failed to serialize newly created index.")?;
             file_handle
-                .write_all(serialized.as_bytes())
+                .write_all(&encoded)
                 .context("Failed to write index data to file.")?;
+            file_handle.commit()?;
+            os_interaction::register_index(
+                user_data_directory,
+                index_name,
+                source_directory,
+                &traversal_options,
+                &new_index,
+                Some(build_start.elapsed()),
+            )?;
+            Ok(())
+        }
+        Command::Refresh { index_name } => {
+            let index_file_path =
+                os_interaction::get_index_file_path(user_data_directory.clone(), index_name)?;
+            let registry = os_interaction::get_registry(user_data_directory.clone())?;
+            let entry = registry.get(index_name).with_context(|| {
+                format!("Index {index_name} is not in the registry, create it again to refresh it.")
+            })?;
+            let mut index = os_interaction::load_index(&index_file_path)?;
+
+            let parser_registry = ParserRegistry::default();
+            let file_paths = path_resolver::collect_valid_paths(
+                &entry.source_directory,
+                &parser_registry,
+                &entry.traversal_options,
+            )?;
+            let summary = utils::refresh_index(&mut index, &file_paths, &parser_registry);
+            os_interaction::save_index(&index_file_path, &index)?;
+            os_interaction::register_index(
+                user_data_directory,
+                index_name,
+                &entry.source_directory,
+                &entry.traversal_options,
+                &index,
+                None,
+            )?;
+            println!("Refreshed {index_name}: {summary}.");
+            Ok(())
+        }
+        Command::Watch {
+            index_name,
+            poll,
+            debounce_ms,
+        } => {
+            let index_file_path =
+                os_interaction::get_index_file_path(user_data_directory.clone(), index_name)?;
+            let registry = os_interaction::get_registry(user_data_directory.clone())?;
+            let entry = registry.get(index_name).with_context(|| {
+                format!("Index {index_name} is not in the registry, create it again to watch it.")
+            })?;
+            let mut index = os_interaction::load_index(&index_file_path)?;
+            let parser_registry = ParserRegistry::default();
+
+            // start watching before catching up so that no change slips through
+            let directory_watcher = DirectoryWatcher::new(
+                &entry.source_directory,
+                *poll,
+                watcher::DEFAULT_POLL_INTERVAL,
+            )?;
+            let file_paths = path_resolver::collect_valid_paths(
+                &entry.source_directory,
+                &parser_registry,
+                &entry.traversal_options,
+            )?;
+            let mut summary = utils::refresh_index(&mut index, &file_paths, &parser_registry);
+            println!(
+                "Watching {} with {} change detection, press Ctrl-C to stop.",
+                entry.source_directory.display(),
+                directory_watcher.backend()
+            );
+
+            let debounce = Duration::from_millis(*debounce_ms);
+            loop {
+                if summary.has_changes() {
+                    os_interaction::save_index(&index_file_path, &index)?;
+                    os_interaction::register_index(
+                        user_data_directory.clone(),
+                        index_name,
+                        &entry.source_directory,
+                        &entry.traversal_options,
+                        &index,
+                        None,
+                    )?;
+                    println!("Updated {index_name}: {summary}.");
+                }
+                let Some(changed) = directory_watcher.next_changes(debounce) else {
+                    return Ok(());
+                };
+                summary = watcher::apply_changes(
+                    &mut index,
+                    &entry.source_directory,
+                    &changed,
+                    &parser_registry,
+                    &entry.traversal_options,
+                )?;
+            }
+        }
+        Command::Export { index_name, output } => {
+            let index_file_path =
+                os_interaction::get_index_file_path(user_data_directory, index_name)?;
+            let index = os_interaction::load_index(&index_file_path)?;
+            match output {
+                Some(output) => {
+                    let file = File::create(output)
+                        .with_context(|| format!("Failed to create {}", output.display()))?;
+                    os_interaction::export_index_json(&index, BufWriter::new(file))
+                }
+                None => os_interaction::export_index_json(&index, std::io::stdout().lock()),
+            }
+        }
+        Command::Info {
+            index_name,
+            top,
+            json,
+        } => {
+            let index_file_path =
+                os_interaction::get_index_file_path(user_data_directory.clone(), index_name)?;
+            let registry = os_interaction::get_registry(user_data_directory)?;
+            let index = os_interaction::load_index(&index_file_path)?;
+            let size_on_disk = fs::metadata(&index_file_path)
+                .with_context(|| format!("Failed to read {}", index_file_path.display()))?
+                .len();
+
+            let statistics = IndexStatistics::new(
+                index_name,
+                &index,
+                registry.get(index_name),
+                size_on_disk,
+                &ParserRegistry::default(),
+                *top,
+            );
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&statistics)?);
+            } else {
+                print!("{statistics}");
+            }
+            Ok(())
+        }
+        Command::Migrate { index_names } => {
+            let index_names = if index_names.is_empty() {
+                os_interaction::get_index_names(user_data_directory.clone())?
+                    .into_iter()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .collect()
+            } else {
+                index_names.clone()
+            };
+
+            let mut failed = false;
+            for index_name in index_names {
+                match os_interaction::migrate_index(user_data_directory.clone(), &index_name) {
+                    Ok(migration) => println!("{index_name}: {migration}."),
+                    Err(err) => {
+                        eprintln!("{index_name}: {err:#}");
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                anyhow::bail!("Some indexes could not be migrated.");
+            }
+            Ok(())
+        }
+        Command::Delete { index_name } => {
+            os_interaction::delete_index(user_data_directory, index_name)?;
+            println!("Deleted {index_name}.");
+            Ok(())
+        }
+        Command::Rename {
+            index_name,
+            new_name,
+            force,
+        } => {
+            os_interaction::rename_index(user_data_directory, index_name, new_name, *force)?;
+            println!("Renamed {index_name} to {new_name}.");
+            Ok(())
+        }
+        Command::Copy {
+            index_name,
+            new_name,
+            force,
+        } => {
+            os_interaction::copy_index(user_data_directory, index_name, new_name, *force)?;
+            println!("Copied {index_name} to {new_name}.");
             Ok(())
         }
         Command::List => {
-            let index_names = os_interaction::get_index_names(user_data_directory)?;
+            let index_names = os_interaction::get_index_names(user_data_directory.clone())?;
+            let registry = os_interaction::get_registry(user_data_directory)?;
 
             if index_names.is_empty() {
                 println!("No valid index was found!");
             } else {
                 for index_name in index_names {
-                    println!("{}", index_name.display());
+                    match registry.get(&index_name.to_string_lossy()) {
+                        Some(entry) => println!(
+                            "{}\t{}",
+                            index_name.display(),
+                            entry.source_directory.display()
+                        ),
+                        None => println!("{}", index_name.display()),
+                    }
                 }
             }
             Ok(())
         }
+        Command::Formats => {
+            let parser_registry = ParserRegistry::default();
+            for parser in parser_registry.parsers() {
+                println!("{}\t.{}", parser.name(), parser.extensions().join(", ."));
+            }
+            Ok(())
+        }
     }
 }
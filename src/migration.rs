@@ -0,0 +1,245 @@
+use anyhow::{self, Context};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::fingerprint::FileFingerprint;
+use crate::index_format::{self, IndexFormatError, POSITIONS_VERSION};
+use crate::term_frequency::TermFrequency;
+use crate::utils::{INDEX_FORMAT_VERSION, Index};
+
+/// Layouts of the JSON index files written before the binary format.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacyIndex {
+    /// Inverted index with a document table
+    Documents(Index),
+    /// One term frequency table per document
+    TermFrequencies {
+        term_frequencies: Vec<LegacyTermFrequency>,
+    },
+}
+
+#[derive(Deserialize)]
+struct LegacyTermFrequency {
+    document_path: PathBuf,
+    #[serde(default)]
+    title: Option<String>,
+    term_freq: HashMap<String, u32>,
+}
+
+impl From<LegacyTermFrequency> for TermFrequency {
+    fn from(legacy: LegacyTermFrequency) -> Self {
+        let mut tf = TermFrequency::new(legacy.document_path);
+        tf.title = legacy.title;
+        // terms used to keep their case, the tokenizer now lowercases them
+        for (term, count) in legacy.term_freq {
+            *tf.term_freq.entry(term.to_lowercase()).or_default() += count;
+            tf.length += count;
+        }
+        tf
+    }
+}
+
+/// Converts a JSON index of any earlier layout. None of them recorded token positions, so
+/// the next refresh re-parses every document once.
+pub fn migrate_json(json: &str) -> Result<Index, anyhow::Error> {
+    let legacy: LegacyIndex =
+        serde_json::from_str(json).context("Unrecognized JSON index layout.")?;
+    Ok(match legacy {
+        LegacyIndex::Documents(index) => without_fingerprints(index),
+        LegacyIndex::TermFrequencies { term_frequencies } => Index::from_term_frequencies(
+            term_frequencies
+                .into_iter()
+                .map(TermFrequency::from)
+                .collect(),
+        ),
+    })
+}
+
+/// Clears the fingerprints of every document, so that the next refresh re-parses them.
+fn without_fingerprints(mut index: Index) -> Index {
+    for document in &mut index.documents {
+        document.fingerprint = FileFingerprint::default();
+    }
+    index
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Migration {
+    UpToDate,
+    /// Converted from a JSON index
+    FromJson,
+    /// Converted from an older binary format version
+    FromVersion(u32),
+}
+
+impl Migration {
+    /// Whether the migrated index lacks token positions until it is refreshed.
+    pub fn needs_refresh(&self) -> bool {
+        match self {
+            Migration::UpToDate => false,
+            Migration::FromJson => true,
+            Migration::FromVersion(version) => *version < POSITIONS_VERSION,
+        }
+    }
+}
+
+impl std::fmt::Display for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Migration::UpToDate => write!(f, "already up to date")?,
+            Migration::FromJson => {
+                write!(
+                    f,
+                    "upgraded from JSON to format version {INDEX_FORMAT_VERSION}"
+                )?;
+            }
+            Migration::FromVersion(version) => write!(
+                f,
+                "upgraded from format version {version} to {INDEX_FORMAT_VERSION}"
+            )?,
+        }
+        if self.needs_refresh() {
+            write!(f, ", refresh it to enable phrase queries")?;
+        }
+        Ok(())
+    }
+}
+
+/// Brings a binary index file to the current format version, `None` when it already is.
+pub fn migrate_binary(bytes: &[u8]) -> Result<Option<Index>, anyhow::Error> {
+    match index_format::read_format_version(bytes)? {
+        INDEX_FORMAT_VERSION => Ok(None),
+        version if version < INDEX_FORMAT_VERSION => {
+            let index = index_format::decode_index_of_any_version(bytes)
+                .context("No migration is available for this index, create it again.")?;
+            if version < POSITIONS_VERSION {
+                return Ok(Some(without_fingerprints(index)));
+            }
+            Ok(Some(index))
+        }
+        version if version > INDEX_FORMAT_VERSION => {
+            Err(IndexFormatError::NewerVersion { found: version }.into())
+        }
+        version => Err(IndexFormatError::IncompatibleVersion { found: version })
+            .context("No migration is available for this index, create it again."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::fingerprint::FileFingerprint;
+    use crate::index_format::{MAGIC, encode_index, write_varint};
+    use crate::migration::{Migration, migrate_binary, migrate_json};
+    use crate::term_frequency::TermFrequency;
+    use crate::utils::Index;
+
+    #[test]
+    fn term_frequency_layout_is_migrated() {
+        let json = r#"{
+            "term_frequencies": [
+                {"document_path": "rome.xml", "term_freq": {"Rome": 2, "rome": 1, "empire": 1}},
+                {"document_path": "paris.xml", "title": "Paris", "term_freq": {"paris": 3}, "length": 3}
+            ],
+            "inverse_document_frequency": {"Rome": 0.3, "rome": 0.3, "empire": 0.3, "paris": 0.3}
+        }"#;
+
+        let index = migrate_json(json).unwrap();
+
+        assert_eq!(index.document_count(), 2);
+        assert_eq!(index.documents[0].path, Path::new("rome.xml"));
+        assert_eq!(index.documents[0].length, 4);
+        assert_eq!(index.documents[1].title.as_deref(), Some("Paris"));
+        assert_eq!(index.inverted_index.postings("rome")[0].term_freq, 3);
+        assert!(index.inverted_index.postings("Rome").is_empty());
+        assert_eq!(index.average_document_length, 3.5);
+    }
+
+    #[test]
+    fn document_table_layout_is_migrated() {
+        let mut tf = TermFrequency::new("rome.xml".into());
+        tf.update("rome");
+        let index = Index::from_term_frequencies(vec![tf]);
+        let mut json = serde_json::to_value(&index).unwrap();
+        // fingerprints were added later
+        json["documents"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("fingerprint");
+
+        let migrated = migrate_json(&json.to_string()).unwrap();
+
+        assert_eq!(migrated.documents[0].path, Path::new("rome.xml"));
+        assert_eq!(migrated.inverted_index.postings("rome").len(), 1);
+    }
+
+    #[test]
+    fn unknown_layouts_are_rejected() {
+        assert!(migrate_json(r#"{"something": []}"#).is_err());
+        assert!(migrate_json("not json").is_err());
+    }
+
+    /// A version 7 file holding a single document with the term "rome".
+    fn create_version_7_index() -> Vec<u8> {
+        let mut documents = 8u64.to_le_bytes().to_vec();
+        write_varint(&mut documents, 8);
+        documents.extend_from_slice(b"rome.xml");
+        for value in [0, 1, 5, 3] {
+            write_varint(&mut documents, value);
+        }
+        documents.extend_from_slice(&9u64.to_le_bytes());
+        let mut dictionary = 8u64.to_le_bytes().to_vec();
+        write_varint(&mut dictionary, 4);
+        dictionary.extend_from_slice(b"rome");
+        for value in [1, 0, 2] {
+            write_varint(&mut dictionary, value);
+        }
+        let postings = [0, 1];
+
+        let mut bytes = MAGIC.to_vec();
+        for value in [7u32, 1, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&1f32.to_le_bytes());
+        let documents_offset = 48;
+        let dictionary_offset = documents_offset + documents.len();
+        for offset in [
+            documents_offset,
+            dictionary_offset,
+            dictionary_offset + dictionary.len(),
+        ] {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+        bytes.extend(documents);
+        bytes.extend(dictionary);
+        bytes.extend(postings);
+        bytes
+    }
+
+    #[test]
+    fn index_without_positions_is_migrated() {
+        let index = migrate_binary(&create_version_7_index()).unwrap().unwrap();
+
+        assert_eq!(index.documents[0].path, Path::new("rome.xml"));
+        assert_eq!(index.documents[0].length, 1);
+        // positions are only recorded once the documents are parsed again
+        assert_eq!(index.documents[0].fingerprint, FileFingerprint::default());
+        let postings = index.inverted_index.postings("rome");
+        assert_eq!(postings[0].term_freq, 1);
+        assert!(postings[0].positions.is_empty());
+        assert!(Migration::FromVersion(7).to_string().contains("refresh"));
+    }
+
+    #[test]
+    fn current_binary_index_needs_no_migration() {
+        let bytes = encode_index(&Index::new()).unwrap();
+
+        assert!(migrate_binary(&bytes).unwrap().is_none());
+        assert!(migrate_binary(b"TRUSTAMI\x01\x00\x00\x00").is_err());
+        let newer = migrate_binary(b"TRUSTAMI\xff\x00\x00\x00").unwrap_err();
+        assert!(format!("{newer:#}").contains("newer version"));
+    }
+}
@@ -1,15 +1,23 @@
 //use dirs;
 use anyhow::{self, Context};
-use std::ffi::OsString;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
-use std::io::{BufRead, ErrorKind, Read};
+use std::io::{self, BufRead, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
-use serde::{Serialize, Deserialize};
-use serde_json;
+
+use crate::index_format;
+use crate::migration::{self, Migration};
+use crate::path_resolver::TraversalOptions;
+use crate::utils::{INDEX_FORMAT_VERSION, Index};
 
 const APPLICATION_DATA_DIRECTORY_NAME: &str = "trustami_application_data";
-const INDEX_FILENAME: &str = "index.json";
+const INDEX_FILENAME: &str = "index.bin";
+/// Indexes were stored as JSON before the binary format
+const LEGACY_INDEX_FILENAME: &str = "index.json";
 const REGISTRY_FILENAME: &str = "registry.json";
 
 #[derive(Debug, Error)]
@@ -18,15 +26,283 @@ enum FolderStructureError<'a> {
     MissingFolderError(&'a str),
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Error)]
+pub enum IndexLookupError {
+    #[error("No index named {name} was found. Available indexes: {}", format_index_names(.available))]
+    IndexNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error(
+        "Index {name} was built by an incompatible version of trustami, run `trustami migrate {name}` to upgrade it"
+    )]
+    IncompatibleVersion { name: String },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum IndexNameError {
+    #[error("Index names cannot be empty")]
+    Empty,
+    #[error("Index name {0} cannot start with a dot")]
+    LeadingDot(String),
+    #[error("Index name {0} may only contain letters, digits, dots, dashes and underscores")]
+    InvalidCharacter(String),
+}
+
+/// Index names become directory names, so they are restricted to characters that are
+/// valid and unambiguous on every platform.
+pub fn validate_index_name(index_name: &str) -> Result<(), IndexNameError> {
+    if index_name.is_empty() {
+        return Err(IndexNameError::Empty);
+    }
+    if index_name.starts_with('.') {
+        return Err(IndexNameError::LeadingDot(index_name.to_string()));
+    }
+    if !index_name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        return Err(IndexNameError::InvalidCharacter(index_name.to_string()));
+    }
+    Ok(())
+}
+
+/// The operation was refused rather than failed, the CLI exits with a distinct status.
+#[derive(Debug, Error)]
+pub enum RefusedError {
+    #[error("An index named {name} already exists, pass --force to replace it")]
+    IndexExists { name: String },
+    #[error(
+        "An index named {name} already exists and standard input is not a terminal, pass --force to replace it"
+    )]
+    CannotPrompt { name: String },
+    #[error("Operation aborted by user.")]
+    Aborted,
+}
+
+/// What to do when an index with the same name already exists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverwritePolicy {
+    /// Ask for confirmation on the given input
+    Prompt,
+    /// Refuse because nobody can answer the prompt
+    NonInteractive,
+    Replace,
+    Refuse,
+}
+
+fn format_index_names(names: &[String]) -> String {
+    if names.is_empty() {
+        String::from("none")
+    } else {
+        names.join(", ")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Registry(Vec<RegistryEntry>);
 
-#[derive(Serialize, Deserialize)]
-struct RegistryEntry {
-    index_name: String,
-    path: PathBuf,
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegistryEntry {
+    pub index_name: String,
+    /// Location of the index file on disk
+    pub path: PathBuf,
+    /// Directory whose documents were indexed
+    pub source_directory: PathBuf,
+    /// Seconds since the unix epoch
+    pub created_at: u64,
+    /// Seconds since the unix epoch
+    pub last_refreshed_at: u64,
+    pub document_count: usize,
+    pub term_count: usize,
+    pub format_version: u32,
+    /// Options used to collect the indexed files, reused on refresh
+    #[serde(default)]
+    pub traversal_options: TraversalOptions,
+    /// Time taken to create the index, unknown for indexes created by older versions
+    #[serde(default)]
+    pub build_duration_ms: Option<u64>,
 }
 
+impl Registry {
+    pub fn entries(&self) -> &[RegistryEntry] {
+        &self.0
+    }
+
+    pub fn get(&self, index_name: &str) -> Option<&RegistryEntry> {
+        self.0.iter().find(|entry| entry.index_name == index_name)
+    }
+
+    fn remove(&mut self, index_name: &str) -> Option<RegistryEntry> {
+        let position = self
+            .0
+            .iter()
+            .position(|entry| entry.index_name == index_name)?;
+        Some(self.0.remove(position))
+    }
+
+    /// Replaces the entry with the same name. Entries without a build duration come from a
+    /// refresh and keep the original creation time and build duration, rebuilt indexes
+    /// start over.
+    fn upsert(&mut self, mut entry: RegistryEntry) {
+        if let Some(existing) = self
+            .0
+            .iter_mut()
+            .find(|existing| existing.index_name == entry.index_name)
+        {
+            if entry.build_duration_ms.is_none() {
+                entry.created_at = existing.created_at;
+                entry.build_duration_ms = existing.build_duration_ms;
+            }
+            *existing = entry;
+        } else {
+            self.0.push(entry);
+        }
+    }
+}
+
+/// A file written next to its destination and renamed over it on `commit`, so that the
+/// previous file stays intact until the new one is complete and synced to disk.
+/// Dropping it without committing discards what was written.
+#[derive(Debug)]
+pub struct AtomicFile {
+    destination: PathBuf,
+    temp_path: PathBuf,
+    file: Option<File>,
+    /// Staging directory holding the destination, moved to the second path on commit
+    staged_directory: Option<(PathBuf, PathBuf)>,
+}
+
+/// Renames `from` to `to` and syncs the parent directory to persist the rename itself.
+fn rename_durably(from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+    fs::rename(from, to)
+        .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+
+    // directories cannot be opened for syncing on windows
+    #[cfg(unix)]
+    if let Some(directory) = to.parent() {
+        File::open(directory)
+            .and_then(|directory| directory.sync_all())
+            .with_context(|| format!("Failed to sync {}", directory.display()))?;
+    }
+    Ok(())
+}
+
+/// Hidden name of the directory a new index is built in, which no index name can take.
+fn staging_directory_name(index_name: &Path) -> OsString {
+    let mut name = OsString::from(".");
+    name.push(index_name.as_os_str());
+    name.push(format!(".{}.tmp", std::process::id()));
+    name
+}
+
+fn is_staging_directory_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.') && name.ends_with(".tmp")
+}
+
+impl AtomicFile {
+    pub fn create<P>(destination: P) -> Result<Self, anyhow::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let destination = destination.as_ref().to_path_buf();
+        let file_name = destination
+            .file_name()
+            .context("Destination has no file name.")?
+            .to_string_lossy();
+        // the same directory keeps the rename on one filesystem, the process id keeps
+        // concurrent writers apart
+        let temp_path =
+            destination.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+        let file = File::create(&temp_path)
+            .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+
+        Ok(Self {
+            destination,
+            temp_path,
+            file: Some(file),
+            staged_directory: None,
+        })
+    }
+
+    /// Like `create`, for a file in a staging directory that replaces `directory` once the
+    /// file is committed. Dropping it without committing removes the staging directory.
+    fn create_in_staging_directory<P>(
+        destination: P,
+        directory: PathBuf,
+    ) -> Result<Self, anyhow::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let staging_directory = destination
+            .as_ref()
+            .parent()
+            .context("Destination has no parent directory.")?
+            .to_path_buf();
+        let mut file = Self::create(destination).inspect_err(|_| {
+            let _ = fs::remove_dir_all(&staging_directory);
+        })?;
+        file.staged_directory = Some((staging_directory, directory));
+        Ok(file)
+    }
+
+    pub fn commit(mut self) -> Result<(), anyhow::Error> {
+        let file = self.file.take().context("File was already committed.")?;
+        file.sync_all()
+            .with_context(|| format!("Failed to sync {}", self.temp_path.display()))?;
+        drop(file);
+        rename_durably(&self.temp_path, &self.destination)?;
+
+        if let Some((staging_directory, directory)) = &self.staged_directory {
+            rename_durably(staging_directory, directory)?;
+            self.staged_directory = None;
+        }
+        Ok(())
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("file was already committed"))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+        if let Some((staging_directory, _)) = self.staged_directory.take() {
+            let _ = fs::remove_dir_all(staging_directory);
+        }
+    }
+}
+
+/// Replaces the content of a file through an `AtomicFile`.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<(), anyhow::Error> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    file.commit()
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
 
 fn create_application_data_directory(
     mut user_data_directory: PathBuf,
@@ -44,7 +320,6 @@ fn create_application_data_directory(
     File::create(registry_path)
         .with_context(|| format!("Could not create {} file", REGISTRY_FILENAME))?;
 
-
     Ok(user_data_directory)
 }
 
@@ -63,72 +338,158 @@ where
     Ok(index_path)
 }
 
-fn update_registry_file<P>(
+fn read_registry_file(application_data_path: &Path) -> Result<Registry, anyhow::Error> {
+    let registry_path = application_data_path.join(REGISTRY_FILENAME);
+    let mut buf = String::new();
+
+    let mut registry = match File::open(&registry_path) {
+        Ok(registry) => registry,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Registry::default()),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to open file at {}", registry_path.display()));
+        }
+    };
+    registry
+        .read_to_string(&mut buf)
+        .context("Failed to read registry file.")?;
+
+    // the registry file is created empty along with the application data directory
+    if buf.trim().is_empty() {
+        return Ok(Registry::default());
+    }
+
+    serde_json::from_str(&buf).context("Failed to convert file content to registry struct")
+}
+
+fn update_registry_file(
     application_data_path: PathBuf,
+    entry: RegistryEntry,
+) -> Result<(), anyhow::Error> {
+    modify_registry_file(&application_data_path, |registry| registry.upsert(entry))
+}
+
+fn modify_registry_file<F>(application_data_path: &Path, modify: F) -> Result<(), anyhow::Error>
+where
+    F: FnOnce(&mut Registry),
+{
+    let registry_path = application_data_path.join(REGISTRY_FILENAME);
+    let mut registry = read_registry_file(application_data_path)?;
+    modify(&mut registry);
+
+    let serialized =
+        serde_json::to_string(&registry).context("Failed to serialize registry struct.")?;
+    write_atomically(&registry_path, serialized.as_bytes())
+        .context("Failed to write registry file.")
+}
+
+pub fn get_registry(user_data_directory: PathBuf) -> Result<Registry, anyhow::Error> {
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    read_registry_file(&application_data_path)
+}
+
+/// Records a freshly written index in the registry, returning the stored entry. A missing
+/// `build_duration` keeps the one recorded when the index was created.
+pub fn register_index<P, Q>(
+    user_data_directory: PathBuf,
     index_name: P,
-) -> Result<(), anyhow::Error>
+    source_directory: Q,
+    traversal_options: &TraversalOptions,
+    index: &Index,
+    build_duration: Option<Duration>,
+) -> Result<RegistryEntry, anyhow::Error>
 where
     P: AsRef<Path>,
+    Q: AsRef<Path>,
 {
-    let registry_path = application_data_path.join(REGISTRY_FILENAME);
-    let mut buf = String::new();
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    let name = index_name.as_ref().display().to_string();
+    let now = unix_timestamp();
+    let entry = RegistryEntry {
+        index_name: name.clone(),
+        path: application_data_path.join(&index_name).join(INDEX_FILENAME),
+        source_directory: source_directory.as_ref().to_path_buf(),
+        created_at: now,
+        last_refreshed_at: now,
+        document_count: index.document_count(),
+        term_count: index.term_count(),
+        format_version: INDEX_FORMAT_VERSION,
+        traversal_options: traversal_options.clone(),
+        build_duration_ms: build_duration.map(|duration| duration.as_millis() as u64),
+    };
 
-    let registry = File::open(registry_path)
-        .with_context(|| format!("Failed to open file at {}", registry_path.display()))?;
-    registry.read_to_string(&mut buf)
-        .context("Failed to read registry file.")?;
+    update_registry_file(application_data_path.clone(), entry)?;
 
-    serde_json::from_str(&buf)
-        .context("Failed to convert file content to registry struct")?;
-    
+    let registry = read_registry_file(&application_data_path)?;
+    registry
+        .get(&name)
+        .cloned()
+        .context("Registry entry was not persisted.")
 }
 
+/// Prepares the file a new index is written to, applying `overwrite_policy` when the
+/// index exists. The existing index stays untouched until the returned file is committed.
 pub fn create_index_file<P, R>(
     user_data_directory: PathBuf,
     index_name: P,
+    overwrite_policy: OverwritePolicy,
     mut reader: R,
-) -> Result<File, anyhow::Error>
+) -> Result<AtomicFile, anyhow::Error>
 where
     P: AsRef<Path>,
     R: BufRead,
 {
+    validate_index_name(&index_name.as_ref().to_string_lossy())?;
     let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
     if !application_data_path.exists() {
         let _ = create_application_data_directory(user_data_directory)?;
     }
     let index_directory = application_data_path.join(&index_name);
     if !index_directory.exists() {
-        let _ = create_index_directory(application_data_path, index_name)?;
+        // built aside, so that a failed or interrupted build leaves no index directory
+        let staging_directory = create_index_directory(
+            application_data_path,
+            staging_directory_name(index_name.as_ref()),
+        )?;
+        return AtomicFile::create_in_staging_directory(
+            staging_directory.join(INDEX_FILENAME),
+            index_directory,
+        );
     }
 
     let file_path = index_directory.join(INDEX_FILENAME);
-    //let file_handle = File::create_new(&file_path).context("Failed to create an index file.")?;
-
-    let file_handle = match File::create_new(&file_path) {
-        Ok(file_handle) => file_handle,
-        Err(err) => match err.kind() {
-            ErrorKind::AlreadyExists => {
+    if file_path.exists() {
+        let name = index_name.as_ref().display().to_string();
+        match overwrite_policy {
+            OverwritePolicy::Replace => {}
+            OverwritePolicy::Refuse => return Err(RefusedError::IndexExists { name }.into()),
+            OverwritePolicy::NonInteractive => {
+                return Err(RefusedError::CannotPrompt { name }.into());
+            }
+            OverwritePolicy::Prompt => {
                 println!(
                     "An existing index with the same name was found: do you want to replace it? [y/N]"
                 );
                 let mut buffer = String::new();
                 loop {
                     buffer.clear();
-                    reader.read_line(&mut buffer)?;
+                    // nothing more to read, nobody is going to answer
+                    if reader.read_line(&mut buffer)? == 0 {
+                        return Err(RefusedError::Aborted.into());
+                    }
                     let input = buffer.trim().to_lowercase();
                     if input == "y" {
-                        break File::create(&file_path)?;
+                        break;
                     } else if input == "n" {
-                        return Err(err).context("Operation aborted by user.");
+                        return Err(RefusedError::Aborted.into());
                     }
                     println!("Please insert [y/N] to replace existing index or abort operation.");
                 }
             }
-            _ => return Err(err).context("Generic error while creating the index file"),
-        },
-    };
+        }
+    }
 
-    Ok(file_handle)
+    AtomicFile::create(&file_path)
 }
 
 pub fn get_index_names(user_data_directory: PathBuf) -> Result<Vec<OsString>, anyhow::Error> {
@@ -141,7 +502,7 @@ pub fn get_index_names(user_data_directory: PathBuf) -> Result<Vec<OsString>, an
             .filter_map(|e| match e {
                 Ok(entry) => match entry.file_type() {
                     Ok(file_type) => {
-                        if file_type.is_dir() {
+                        if file_type.is_dir() && !is_staging_directory_name(&entry.file_name()) {
                             Some(entry.file_name())
                         } else {
                             None
@@ -173,16 +534,299 @@ pub fn get_index_names(user_data_directory: PathBuf) -> Result<Vec<OsString>, an
     }
 }
 
+/// Whether `index_name` is exactly the name of an index directory. Existing indexes are
+/// looked up rather than validated, so names predating validation still resolve while
+/// paths such as `..` never do.
+fn has_index_directory<P>(user_data_directory: &Path, index_name: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    get_index_names(user_data_directory.to_path_buf())
+        .unwrap_or_default()
+        .iter()
+        .any(|name| name == index_name.as_ref().as_os_str())
+}
+
+pub fn get_index_file_path<P>(
+    user_data_directory: PathBuf,
+    index_name: P,
+) -> Result<PathBuf, anyhow::Error>
+where
+    P: AsRef<Path>,
+{
+    if !has_index_directory(&user_data_directory, &index_name) {
+        return Err(index_not_found(user_data_directory, index_name));
+    }
+    let index_file_path = user_data_directory
+        .join(APPLICATION_DATA_DIRECTORY_NAME)
+        .join(&index_name)
+        .join(INDEX_FILENAME);
+
+    if index_file_path.is_file() {
+        return Ok(index_file_path);
+    }
+    if index_file_path
+        .with_file_name(LEGACY_INDEX_FILENAME)
+        .is_file()
+    {
+        return Err(IndexLookupError::IncompatibleVersion {
+            name: index_name.as_ref().display().to_string(),
+        }
+        .into());
+    }
+
+    Err(index_not_found(user_data_directory, index_name))
+}
+
+fn index_not_found<P>(user_data_directory: PathBuf, index_name: P) -> anyhow::Error
+where
+    P: AsRef<Path>,
+{
+    let available = get_index_names(user_data_directory)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+
+    IndexLookupError::IndexNotFound {
+        name: index_name.as_ref().display().to_string(),
+        available,
+    }
+    .into()
+}
+
+pub fn load_index<P>(index_file_path: P) -> Result<Index, anyhow::Error>
+where
+    P: AsRef<Path>,
+{
+    let index_file_path = index_file_path.as_ref();
+    let bytes = fs::read(index_file_path)
+        .with_context(|| format!("Failed to read index file at {}", index_file_path.display()))?;
+
+    index_format::decode_index(&bytes)
+        .with_context(|| format!("Failed to load index file at {}", index_file_path.display()))
+}
+
+pub fn save_index<P>(index_file_path: P, index: &Index) -> Result<(), anyhow::Error>
+where
+    P: AsRef<Path>,
+{
+    let index_file_path = index_file_path.as_ref();
+    let encoded = index_format::encode_index(index).context("Failed to serialize index.")?;
+
+    write_atomically(index_file_path, &encoded).context("Failed to write index data to file.")
+}
+
+/// Upgrades an index stored in an earlier format in place, updating its registry entry.
+pub fn migrate_index<P>(
+    user_data_directory: PathBuf,
+    index_name: P,
+) -> Result<Migration, anyhow::Error>
+where
+    P: AsRef<Path>,
+{
+    if !has_index_directory(&user_data_directory, &index_name) {
+        return Err(index_not_found(user_data_directory, index_name));
+    }
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    let index_directory = application_data_path.join(&index_name);
+    let index_file_path = index_directory.join(INDEX_FILENAME);
+    let legacy_file_path = index_directory.join(LEGACY_INDEX_FILENAME);
+
+    let (index, migration) = if index_file_path.is_file() {
+        let bytes = fs::read(&index_file_path).with_context(|| {
+            format!("Failed to read index file at {}", index_file_path.display())
+        })?;
+        match migration::migrate_binary(&bytes)? {
+            Some(index) => {
+                let version = index_format::read_format_version(&bytes)?;
+                (index, Migration::FromVersion(version))
+            }
+            None => return Ok(Migration::UpToDate),
+        }
+    } else if legacy_file_path.is_file() {
+        let json = fs::read_to_string(&legacy_file_path).with_context(|| {
+            format!(
+                "Failed to read index file at {}",
+                legacy_file_path.display()
+            )
+        })?;
+        (migration::migrate_json(&json)?, Migration::FromJson)
+    } else {
+        return Err(index_not_found(user_data_directory, index_name));
+    };
+
+    save_index(&index_file_path, &index)?;
+    if migration == Migration::FromJson {
+        fs::remove_file(&legacy_file_path)
+            .with_context(|| format!("Failed to remove {}", legacy_file_path.display()))?;
+    }
+
+    let registry = read_registry_file(&application_data_path)?;
+    if let Some(entry) = registry.get(&index_name.as_ref().to_string_lossy()) {
+        register_index(
+            user_data_directory,
+            index_name,
+            &entry.source_directory,
+            &entry.traversal_options,
+            &index,
+            None,
+        )?;
+    }
+    Ok(migration)
+}
+
+/// Removes an index directory along with its registry entry.
+pub fn delete_index(user_data_directory: PathBuf, index_name: &str) -> Result<(), anyhow::Error> {
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    let index_directory = application_data_path.join(index_name);
+    let registry = read_registry_file(&application_data_path)?;
+    let has_directory = has_index_directory(&user_data_directory, index_name);
+    if !has_directory && registry.get(index_name).is_none() {
+        return Err(index_not_found(user_data_directory, index_name));
+    }
+
+    if has_directory {
+        fs::remove_dir_all(&index_directory)
+            .with_context(|| format!("Failed to remove {}", index_directory.display()))?;
+    }
+    modify_registry_file(&application_data_path, |registry| {
+        registry.remove(index_name);
+    })
+}
+
+/// Checks that `index_name` names an existing index and that `new_name` is free,
+/// deleting the index called `new_name` when `replace` is set.
+fn prepare_destination(
+    user_data_directory: &Path,
+    index_name: &str,
+    new_name: &str,
+    replace: bool,
+) -> Result<(), anyhow::Error> {
+    validate_index_name(new_name)?;
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    if !has_index_directory(user_data_directory, index_name) {
+        return Err(index_not_found(
+            user_data_directory.to_path_buf(),
+            index_name,
+        ));
+    }
+    if index_name == new_name {
+        anyhow::bail!("The source and destination indexes are the same.");
+    }
+
+    let destination_exists = application_data_path.join(new_name).is_dir()
+        || read_registry_file(&application_data_path)?
+            .get(new_name)
+            .is_some();
+    if destination_exists {
+        if !replace {
+            return Err(RefusedError::IndexExists {
+                name: new_name.to_string(),
+            }
+            .into());
+        }
+        delete_index(user_data_directory.to_path_buf(), new_name)?;
+    }
+    Ok(())
+}
+
+/// Registry entry of an index once it is stored under another name.
+fn renamed_entry(
+    application_data_path: &Path,
+    entry: &RegistryEntry,
+    new_name: &str,
+) -> RegistryEntry {
+    RegistryEntry {
+        index_name: new_name.to_string(),
+        path: application_data_path.join(new_name).join(INDEX_FILENAME),
+        ..entry.clone()
+    }
+}
+
+pub fn rename_index(
+    user_data_directory: PathBuf,
+    index_name: &str,
+    new_name: &str,
+    replace: bool,
+) -> Result<(), anyhow::Error> {
+    prepare_destination(&user_data_directory, index_name, new_name, replace)?;
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    let index_directory = application_data_path.join(index_name);
+    let new_directory = application_data_path.join(new_name);
+
+    fs::rename(&index_directory, &new_directory).with_context(|| {
+        format!(
+            "Failed to move {} to {}",
+            index_directory.display(),
+            new_directory.display()
+        )
+    })?;
+    modify_registry_file(&application_data_path, |registry| {
+        if let Some(entry) = registry.remove(index_name) {
+            registry
+                .0
+                .push(renamed_entry(&application_data_path, &entry, new_name));
+        }
+    })
+}
+
+pub fn copy_index(
+    user_data_directory: PathBuf,
+    index_name: &str,
+    new_name: &str,
+    replace: bool,
+) -> Result<(), anyhow::Error> {
+    prepare_destination(&user_data_directory, index_name, new_name, replace)?;
+    let application_data_path = user_data_directory.join(APPLICATION_DATA_DIRECTORY_NAME);
+    let index_directory = application_data_path.join(index_name);
+    let new_directory = create_index_directory(application_data_path.clone(), new_name)?;
+
+    for filename in [INDEX_FILENAME, LEGACY_INDEX_FILENAME] {
+        let source = index_directory.join(filename);
+        if source.is_file() {
+            let content = fs::read(&source)
+                .with_context(|| format!("Failed to read {}", source.display()))?;
+            write_atomically(&new_directory.join(filename), &content)?;
+        }
+    }
+    modify_registry_file(&application_data_path, |registry| {
+        if let Some(entry) = registry.get(index_name) {
+            let entry = RegistryEntry {
+                created_at: unix_timestamp(),
+                ..renamed_entry(&application_data_path, entry, new_name)
+            };
+            registry.0.push(entry);
+        }
+    })
+}
+
+/// Writes an index as JSON, for inspection or use by other tools.
+pub fn export_index_json<W>(index: &Index, writer: W) -> Result<(), anyhow::Error>
+where
+    W: Write,
+{
+    serde_json::to_writer_pretty(writer, index).context("Failed to export index as JSON.")
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::migration::Migration;
     use crate::os_interaction::{
-        create_application_data_directory, create_index_directory, create_index_file, get_index_names, update_registry_file, APPLICATION_DATA_DIRECTORY_NAME, INDEX_FILENAME
+        APPLICATION_DATA_DIRECTORY_NAME, INDEX_FILENAME, IndexNameError, LEGACY_INDEX_FILENAME,
+        OverwritePolicy, REGISTRY_FILENAME, RefusedError, RegistryEntry, copy_index,
+        create_application_data_directory, create_index_directory, create_index_file, delete_index,
+        get_index_file_path, get_index_names, load_index, migrate_index, read_registry_file,
+        rename_index, update_registry_file, validate_index_name,
     };
+    use crate::path_resolver::TraversalOptions;
+    use serde_json;
     use std::{
-        fs::{self, File}, io::Read, path::PathBuf, str::FromStr
+        fs::{self, File},
+        io::{Read, Write},
+        path::{Path, PathBuf},
     };
     use tempfile::{TempDir, tempdir};
-    use serde_json;
 
     fn create_fake_user_data_path() -> (TempDir, PathBuf) {
         let temp = tempdir().unwrap();
@@ -191,6 +835,14 @@ mod tests {
         (temp, path)
     }
 
+    fn create_index(fake_user_data_path: &Path, index_name: &str, content: &[u8]) {
+        let index_directory = fake_user_data_path
+            .join(APPLICATION_DATA_DIRECTORY_NAME)
+            .join(index_name);
+        fs::create_dir_all(&index_directory).unwrap();
+        fs::write(index_directory.join(INDEX_FILENAME), content).unwrap();
+    }
+
     #[test]
     fn application_data_directory_is_created() {
         let (_temp, fake_user_data_path) = create_fake_user_data_path();
@@ -265,11 +917,24 @@ mod tests {
         // useless for this test case
         let user_input = b"y";
 
-        let result = create_index_file(fake_user_data_path, index_dir_name, &user_input[..]);
+        let mut file = create_index_file(
+            fake_user_data_path,
+            index_dir_name,
+            OverwritePolicy::Prompt,
+            &user_input[..],
+        )
+        .unwrap();
+        file.write_all(b"index").unwrap();
 
-        assert!(result.is_ok());
-        assert!(expected_file_path.exists());
+        assert!(!expected_file_path.exists());
+        file.commit().unwrap();
         assert!(expected_file_path.is_file());
+        assert_eq!(
+            fs::read_dir(expected_file_path.parent().unwrap())
+                .unwrap()
+                .count(),
+            1
+        );
     }
 
     #[test]
@@ -281,29 +946,22 @@ mod tests {
             .join(APPLICATION_DATA_DIRECTORY_NAME)
             .join(index_dir_name)
             .join(INDEX_FILENAME);
-        //let mut creation1 = SystemTime::now();
-        //let mut creation2 = SystemTime::now();
         let user_input = b"y";
 
-        let result1 =
-            create_index_file(fake_user_data_path.clone(), index_dir_name, &user_input[..]);
-        //let _ = result1.as_ref().inspect(|f| {
-        //    creation1 = f.metadata().unwrap().created().unwrap();
-        //});
-
-        let result2 =
-            create_index_file(fake_user_data_path.clone(), index_dir_name, &user_input[..]);
-        //let _ = result2.as_ref().inspect(|f| {
-        //    creation2 = f.metadata().unwrap().created().unwrap();
-        //});
-
-        //dbg!(creation1, creation2);
+        create_index(&fake_user_data_path, index_dir_name, b"old");
+        let mut file = create_index_file(
+            fake_user_data_path.clone(),
+            index_dir_name,
+            OverwritePolicy::Prompt,
+            &user_input[..],
+        )
+        .unwrap();
+        file.write_all(b"new").unwrap();
 
-        assert!(result1.is_ok());
-        assert!(result2.is_ok());
-        //assert!(creation1 < creation2);
-        assert!(expected_file_path.exists());
-        assert!(expected_file_path.is_file());
+        // the previous index stays valid until the new one is complete
+        assert_eq!(fs::read(&expected_file_path).unwrap(), b"old");
+        file.commit().unwrap();
+        assert_eq!(fs::read(&expected_file_path).unwrap(), b"new");
     }
 
     #[test]
@@ -317,15 +975,126 @@ mod tests {
             .join(INDEX_FILENAME);
         let user_input = b"n";
 
-        let result1 =
-            create_index_file(fake_user_data_path.clone(), index_dir_name, &user_input[..]);
-        let result2 =
-            create_index_file(fake_user_data_path.clone(), index_dir_name, &user_input[..]);
+        create_index(&fake_user_data_path, index_dir_name, b"old");
+        let result = create_index_file(
+            fake_user_data_path.clone(),
+            index_dir_name,
+            OverwritePolicy::Prompt,
+            &user_input[..],
+        );
 
-        assert!(result1.is_ok());
-        assert!(result2.is_err());
-        assert!(expected_file_path.exists());
-        assert!(expected_file_path.is_file());
+        assert!(result.is_err());
+        assert_eq!(fs::read(&expected_file_path).unwrap(), b"old");
+    }
+
+    #[test]
+    fn overwrite_policies_do_not_prompt() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+
+        let index_dir_name = "my_index_dir";
+        create_index(&fake_user_data_path, index_dir_name, b"old");
+        let create = |overwrite_policy| {
+            // reading the answer would fail the operation
+            create_index_file(
+                fake_user_data_path.clone(),
+                index_dir_name,
+                overwrite_policy,
+                &b"n"[..],
+            )
+        };
+
+        assert!(create(OverwritePolicy::Replace).is_ok());
+        assert!(matches!(
+            create(OverwritePolicy::Refuse)
+                .unwrap_err()
+                .downcast_ref::<RefusedError>(),
+            Some(RefusedError::IndexExists { .. })
+        ));
+        assert!(matches!(
+            create(OverwritePolicy::NonInteractive)
+                .unwrap_err()
+                .downcast_ref::<RefusedError>(),
+            Some(RefusedError::CannotPrompt { .. })
+        ));
+    }
+
+    #[test]
+    fn prompt_without_input_is_refused() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+
+        let index_dir_name = "my_index_dir";
+        create_index(&fake_user_data_path, index_dir_name, b"old");
+        let result = create_index_file(
+            fake_user_data_path,
+            index_dir_name,
+            OverwritePolicy::Prompt,
+            &b"maybe\n"[..],
+        );
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<RefusedError>(),
+            Some(RefusedError::Aborted)
+        ));
+    }
+
+    #[test]
+    fn uncommitted_new_index_leaves_no_directory() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_index(&fake_user_data_path, "existing_index", b"index");
+
+        let mut file = create_index_file(
+            fake_user_data_path.clone(),
+            "new_index",
+            OverwritePolicy::Refuse,
+            &b""[..],
+        )
+        .unwrap();
+        file.write_all(b"partial").unwrap();
+        assert_eq!(
+            get_index_names(fake_user_data_path.clone()).unwrap(),
+            ["existing_index"]
+        );
+        drop(file);
+
+        let entries: Vec<_> =
+            fs::read_dir(fake_user_data_path.join(APPLICATION_DATA_DIRECTORY_NAME))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .filter(|name| name != REGISTRY_FILENAME)
+                .collect();
+        assert_eq!(entries, ["existing_index"]);
+    }
+
+    #[test]
+    fn uncommitted_index_file_is_discarded() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+
+        let index_dir_name = "my_index_dir";
+        let index_directory = fake_user_data_path
+            .join(APPLICATION_DATA_DIRECTORY_NAME)
+            .join(index_dir_name);
+        let user_input = b"y";
+
+        create_index(&fake_user_data_path, index_dir_name, b"old");
+        let mut file = create_index_file(
+            fake_user_data_path.clone(),
+            index_dir_name,
+            OverwritePolicy::Prompt,
+            &user_input[..],
+        )
+        .unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+
+        let entries: Vec<_> = fs::read_dir(&index_directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, [INDEX_FILENAME]);
+        assert_eq!(
+            fs::read(index_directory.join(INDEX_FILENAME)).unwrap(),
+            b"old"
+        );
     }
 
     #[test]
@@ -366,18 +1135,108 @@ mod tests {
         assert_eq!(names.unwrap().len(), 3);
     }
 
+    #[test]
+    fn index_file_path_is_resolved() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+
+        let index_dir_name = "my_index_dir";
+        create_index(&fake_user_data_path, index_dir_name, b"index");
+
+        let path = get_index_file_path(fake_user_data_path.clone(), index_dir_name).unwrap();
+
+        assert_eq!(
+            path,
+            fake_user_data_path
+                .join(APPLICATION_DATA_DIRECTORY_NAME)
+                .join(index_dir_name)
+                .join(INDEX_FILENAME)
+        );
+    }
+
+    #[test]
+    fn missing_index_error_lists_available_names() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+
+        create_index(&fake_user_data_path, "existing_index", b"index");
+
+        let err = get_index_file_path(fake_user_data_path, "missing_index").unwrap_err();
+        let message = err.to_string();
+
+        assert!(message.contains("missing_index"));
+        assert!(message.contains("existing_index"));
+    }
+
+    #[test]
+    fn index_names_do_not_resolve_outside_the_data_directory() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_index(&fake_user_data_path, "existing_index", b"index");
+        // an index file right next to the application data directory
+        let outside = fake_user_data_path.join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join(INDEX_FILENAME), b"index").unwrap();
+
+        for name in ["../outside", "existing_index/..", "."] {
+            assert!(get_index_file_path(fake_user_data_path.clone(), name).is_err());
+            assert!(migrate_index(fake_user_data_path.clone(), name).is_err());
+        }
+    }
+
+    #[test]
+    fn legacy_index_is_reported_and_migrated() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        let index_directory = fake_user_data_path
+            .join(APPLICATION_DATA_DIRECTORY_NAME)
+            .join("old_index");
+        fs::create_dir_all(&index_directory).unwrap();
+        fs::write(
+            index_directory.join(LEGACY_INDEX_FILENAME),
+            r#"{"term_frequencies": [{"document_path": "a.xml", "term_freq": {"rome": 1}}]}"#,
+        )
+        .unwrap();
+
+        let err = get_index_file_path(fake_user_data_path.clone(), "old_index").unwrap_err();
+        assert!(err.to_string().contains("trustami migrate old_index"));
+
+        let migration = migrate_index(fake_user_data_path.clone(), "old_index").unwrap();
+        assert_eq!(migration, Migration::FromJson);
+        assert!(!index_directory.join(LEGACY_INDEX_FILENAME).exists());
+
+        let index_file_path =
+            get_index_file_path(fake_user_data_path.clone(), "old_index").unwrap();
+        assert_eq!(load_index(index_file_path).unwrap().document_count(), 1);
+        assert_eq!(
+            migrate_index(fake_user_data_path, "old_index").unwrap(),
+            Migration::UpToDate
+        );
+    }
+
+    fn create_registry_entry(index_name: &str, created_at: u64) -> RegistryEntry {
+        RegistryEntry {
+            index_name: index_name.to_string(),
+            path: PathBuf::from("/fake/path"),
+            source_directory: PathBuf::from("/fake/source"),
+            created_at,
+            last_refreshed_at: created_at,
+            document_count: 3,
+            term_count: 42,
+            format_version: 1,
+            traversal_options: TraversalOptions::default(),
+            build_duration_ms: Some(1500),
+        }
+    }
 
     #[test]
     fn update_empty_registry() {
         let (_temp, fake_application_data_path) = create_fake_user_data_path();
 
-        let index_name = "my_index";
-        let index_path = PathBuf::from_str("/fake/path");
-
         let registry_path = fake_application_data_path.join("registry.json");
         File::create(&registry_path).unwrap();
 
-        update_registry_file(fake_application_data_path, index_name, index_path).unwrap();
+        update_registry_file(
+            fake_application_data_path,
+            create_registry_entry("my_index", 100),
+        )
+        .unwrap();
 
         let mut registry_content = String::new();
         let mut registry = File::open(&registry_path).unwrap();
@@ -385,12 +1244,203 @@ mod tests {
         let expected = serde_json::json!([
             {
                 "index_name": "my_index",
-                "path": "/fake/path"
+                "path": "/fake/path",
+                "source_directory": "/fake/source",
+                "created_at": 100,
+                "last_refreshed_at": 100,
+                "document_count": 3,
+                "term_count": 42,
+                "format_version": 1,
+                "traversal_options": {
+                    "include": [],
+                    "exclude": [],
+                    "max_depth": null,
+                    "respect_ignore_files": true
+                },
+                "build_duration_ms": 1500
             }
-        ]).to_string();
-        assert_eq!(
-            registry_content,
-            expected
+        ]);
+        let actual: serde_json::Value = serde_json::from_str(&registry_content).unwrap();
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn refreshed_registry_entry_keeps_creation_time() {
+        let (_temp, fake_application_data_path) = create_fake_user_data_path();
+        let refreshed_entry = |index_name: &str, refreshed_at: u64| RegistryEntry {
+            build_duration_ms: None,
+            ..create_registry_entry(index_name, refreshed_at)
+        };
+
+        update_registry_file(
+            fake_application_data_path.clone(),
+            create_registry_entry("my_index", 100),
+        )
+        .unwrap();
+        update_registry_file(
+            fake_application_data_path.clone(),
+            create_registry_entry("other_index", 150),
+        )
+        .unwrap();
+        update_registry_file(
+            fake_application_data_path.clone(),
+            refreshed_entry("my_index", 200),
+        )
+        .unwrap();
+
+        let registry = read_registry_file(&fake_application_data_path).unwrap();
+        let entry = registry.get("my_index").unwrap();
+
+        assert_eq!(registry.entries().len(), 2);
+        assert_eq!(entry.created_at, 100);
+        assert_eq!(entry.last_refreshed_at, 200);
+        assert_eq!(entry.build_duration_ms, Some(1500));
+    }
+
+    #[test]
+    fn rebuilt_registry_entry_gets_a_new_creation_time() {
+        let (_temp, fake_application_data_path) = create_fake_user_data_path();
+
+        update_registry_file(
+            fake_application_data_path.clone(),
+            create_registry_entry("my_index", 100),
+        )
+        .unwrap();
+        update_registry_file(
+            fake_application_data_path.clone(),
+            RegistryEntry {
+                build_duration_ms: Some(700),
+                ..create_registry_entry("my_index", 300)
+            },
         )
+        .unwrap();
+
+        let registry = read_registry_file(&fake_application_data_path).unwrap();
+        let entry = registry.get("my_index").unwrap();
+
+        assert_eq!(entry.created_at, 300);
+        assert_eq!(entry.build_duration_ms, Some(700));
+    }
+
+    fn create_registered_index(fake_user_data_path: &Path, index_name: &str) {
+        create_index(fake_user_data_path, index_name, index_name.as_bytes());
+        let application_data_path = fake_user_data_path.join(APPLICATION_DATA_DIRECTORY_NAME);
+        let entry = RegistryEntry {
+            path: application_data_path.join(index_name).join(INDEX_FILENAME),
+            ..create_registry_entry(index_name, 100)
+        };
+        update_registry_file(application_data_path, entry).unwrap();
+    }
+
+    fn registered_names(fake_user_data_path: &Path) -> Vec<String> {
+        let registry =
+            read_registry_file(&fake_user_data_path.join(APPLICATION_DATA_DIRECTORY_NAME)).unwrap();
+        let mut names: Vec<String> = registry
+            .entries()
+            .iter()
+            .map(|entry| entry.index_name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn index_names_are_validated() {
+        assert!(validate_index_name("docs-2024_v1.2").is_ok());
+        assert_eq!(validate_index_name(""), Err(IndexNameError::Empty));
+        for name in [".", "..", ".hidden"] {
+            assert!(matches!(
+                validate_index_name(name),
+                Err(IndexNameError::LeadingDot(_))
+            ));
+        }
+        for name in ["a/b", "docs/../escape", "a b", "c:\\d"] {
+            assert!(matches!(
+                validate_index_name(name),
+                Err(IndexNameError::InvalidCharacter(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn index_is_deleted_with_its_registry_entry() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_registered_index(&fake_user_data_path, "first");
+        create_registered_index(&fake_user_data_path, "second");
+
+        delete_index(fake_user_data_path.clone(), "first").unwrap();
+
+        assert_eq!(
+            get_index_names(fake_user_data_path.clone()).unwrap(),
+            ["second"]
+        );
+        assert_eq!(registered_names(&fake_user_data_path), ["second"]);
+        assert!(delete_index(fake_user_data_path.clone(), "first").is_err());
+        assert!(delete_index(fake_user_data_path, "..").is_err());
+    }
+
+    #[test]
+    fn indexes_named_before_validation_can_be_managed() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_registered_index(&fake_user_data_path, "my docs");
+        create_registered_index(&fake_user_data_path, "old notes");
+
+        copy_index(fake_user_data_path.clone(), "my docs", "docs", false).unwrap();
+        rename_index(fake_user_data_path.clone(), "my docs", "renamed", false).unwrap();
+        delete_index(fake_user_data_path.clone(), "old notes").unwrap();
+
+        assert_eq!(registered_names(&fake_user_data_path), ["docs", "renamed"]);
+        assert!(copy_index(fake_user_data_path.clone(), "docs", "my docs", false).is_err());
+    }
+
+    #[test]
+    fn index_is_renamed_with_its_registry_entry() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_registered_index(&fake_user_data_path, "old");
+
+        rename_index(fake_user_data_path.clone(), "old", "new", false).unwrap();
+
+        let index_file_path = get_index_file_path(fake_user_data_path.clone(), "new").unwrap();
+        assert_eq!(fs::read(&index_file_path).unwrap(), b"old");
+        assert!(get_index_file_path(fake_user_data_path.clone(), "old").is_err());
+        let registry =
+            read_registry_file(&fake_user_data_path.join(APPLICATION_DATA_DIRECTORY_NAME)).unwrap();
+        assert_eq!(registry.entries().len(), 1);
+        assert_eq!(registry.get("new").unwrap().path, index_file_path);
+        assert_eq!(registry.get("new").unwrap().created_at, 100);
+    }
+
+    #[test]
+    fn index_is_copied_with_its_registry_entry() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_registered_index(&fake_user_data_path, "original");
+
+        copy_index(fake_user_data_path.clone(), "original", "copy", false).unwrap();
+
+        let copy_path = get_index_file_path(fake_user_data_path.clone(), "copy").unwrap();
+        assert_eq!(fs::read(&copy_path).unwrap(), b"original");
+        assert!(get_index_file_path(fake_user_data_path.clone(), "original").is_ok());
+        assert_eq!(registered_names(&fake_user_data_path), ["copy", "original"]);
+    }
+
+    #[test]
+    fn existing_destination_is_only_replaced_when_forced() {
+        let (_temp, fake_user_data_path) = create_fake_user_data_path();
+        create_registered_index(&fake_user_data_path, "first");
+        create_registered_index(&fake_user_data_path, "second");
+
+        let err = rename_index(fake_user_data_path.clone(), "first", "second", false).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RefusedError>(),
+            Some(RefusedError::IndexExists { .. })
+        ));
+        assert!(copy_index(fake_user_data_path.clone(), "first", "second", false).is_err());
+        assert!(rename_index(fake_user_data_path.clone(), "first", "../second", true).is_err());
+
+        rename_index(fake_user_data_path.clone(), "first", "second", true).unwrap();
+
+        let index_file_path = get_index_file_path(fake_user_data_path.clone(), "second").unwrap();
+        assert_eq!(fs::read(index_file_path).unwrap(), b"first");
+        assert_eq!(registered_names(&fake_user_data_path), ["second"]);
     }
 }
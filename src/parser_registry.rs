@@ -0,0 +1,205 @@
+use anyhow::{self, Context};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::parsers::{
+    HtmlParser, MarkdownParser, ParsedDocument, PdfParser, TextParser, XmlParser,
+};
+
+/// Number of leading bytes inspected when sniffing the format of a file without extension.
+pub const SNIFF_LENGTH: usize = 1024;
+
+/// Turns the raw bytes of a document into the text to index.
+pub trait DocumentParser {
+    /// Short name of the format, shown when listing the supported formats.
+    fn name(&self) -> &str;
+
+    /// Lowercase file extensions handled by the parser, without the leading dot.
+    fn extensions(&self) -> &[&str];
+
+    /// Whether the leading bytes of a file look like this format. Used for files that have
+    /// no extension.
+    fn sniff(&self, _content: &[u8]) -> bool {
+        false
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error>;
+}
+
+/// Chooses the parser for a document by extension, falling back to content sniffing.
+/// Parsers registered later take precedence over earlier ones.
+pub struct ParserRegistry {
+    parsers: Vec<Box<dyn DocumentParser>>,
+}
+
+impl Default for ParserRegistry {
+    /// Registry with every built-in parser.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        // the most generic formats go first so that they are sniffed last
+        registry.register(Box::new(TextParser));
+        registry.register(Box::new(MarkdownParser));
+        registry.register(Box::new(XmlParser));
+        registry.register(Box::new(HtmlParser));
+        registry.register(Box::new(PdfParser));
+        registry
+    }
+}
+
+impl ParserRegistry {
+    /// Registry without any parser.
+    pub fn new() -> Self {
+        Self {
+            parsers: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, parser: Box<dyn DocumentParser>) {
+        self.parsers.push(parser);
+    }
+
+    /// Registered parsers, in registration order.
+    pub fn parsers(&self) -> impl Iterator<Item = &dyn DocumentParser> {
+        self.parsers.iter().map(Box::as_ref)
+    }
+
+    fn by_precedence(&self) -> impl Iterator<Item = &dyn DocumentParser> {
+        self.parsers.iter().rev().map(Box::as_ref)
+    }
+
+    pub fn parser_for_extension(&self, extension: &str) -> Option<&dyn DocumentParser> {
+        let extension = extension.to_lowercase();
+        self.by_precedence()
+            .find(|parser| parser.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn parser_for_content(&self, content: &[u8]) -> Option<&dyn DocumentParser> {
+        let sample = &content[..content.len().min(SNIFF_LENGTH)];
+        self.by_precedence().find(|parser| parser.sniff(sample))
+    }
+
+    /// Parser for a file: extensions decide when present, otherwise the leading bytes of
+    /// the file are sniffed.
+    pub fn parser_for_path(&self, path: &Path) -> Option<&dyn DocumentParser> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some(extension) => self.parser_for_extension(extension),
+            None => {
+                let mut sample = Vec::with_capacity(SNIFF_LENGTH);
+                File::open(path)
+                    .and_then(|file| file.take(SNIFF_LENGTH as u64).read_to_end(&mut sample))
+                    .ok()?;
+                self.parser_for_content(&sample)
+            }
+        }
+    }
+
+    pub fn parse_file(&self, path: &Path) -> Result<ParsedDocument, anyhow::Error> {
+        let content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        self.parse_content(path, &content)
+    }
+
+    /// Parser for content already read from `path`, picked like `parser_for_path`.
+    pub fn parser_for_content_of(
+        &self,
+        path: &Path,
+        content: &[u8],
+    ) -> Result<&dyn DocumentParser, anyhow::Error> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some(extension) => self.parser_for_extension(extension),
+            None => self.parser_for_content(content),
+        }
+        .ok_or_else(|| anyhow::anyhow!("No parser supports {}", path.display()))
+    }
+
+    /// Parses content already read from `path`, which is only used to pick the parser.
+    pub fn parse_content(
+        &self,
+        path: &Path,
+        content: &[u8],
+    ) -> Result<ParsedDocument, anyhow::Error> {
+        let parser = self.parser_for_content_of(path, content)?;
+        parser
+            .parse(content)
+            .with_context(|| format!("Failed to parse {} as {}", path.display(), parser.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::tempdir;
+
+    use crate::parser_registry::{DocumentParser, ParserRegistry};
+    use crate::parsers::ParsedDocument;
+
+    struct CsvParser;
+
+    impl DocumentParser for CsvParser {
+        fn name(&self) -> &str {
+            "csv"
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["csv", "txt"]
+        }
+
+        fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+            Ok(ParsedDocument {
+                title: None,
+                text: String::from_utf8_lossy(content).replace(',', " "),
+            })
+        }
+    }
+
+    #[test]
+    fn extensions_are_matched_case_insensitively() {
+        let registry = ParserRegistry::default();
+
+        assert_eq!(registry.parser_for_extension("XML").unwrap().name(), "xml");
+        assert_eq!(registry.parser_for_extension("htm").unwrap().name(), "html");
+        assert!(registry.parser_for_extension("docx").is_none());
+    }
+
+    #[test]
+    fn content_is_sniffed() {
+        let registry = ParserRegistry::default();
+
+        let name_of = |content: &[u8]| registry.parser_for_content(content).unwrap().name();
+
+        assert_eq!(name_of(b"%PDF-1.7\n"), "pdf");
+        assert_eq!(name_of(b"<?xml version=\"1.0\"?><a/>"), "xml");
+        assert_eq!(name_of(b"\n<!DOCTYPE html><html></html>"), "html");
+        assert_eq!(name_of(b"plain words"), "text");
+        assert!(registry.parser_for_content(b"\x00\x01\x02binary").is_none());
+    }
+
+    #[test]
+    fn registered_parsers_take_precedence() {
+        let mut registry = ParserRegistry::default();
+        registry.register(Box::new(CsvParser));
+
+        assert_eq!(registry.parser_for_extension("csv").unwrap().name(), "csv");
+        assert_eq!(registry.parser_for_extension("txt").unwrap().name(), "csv");
+        assert_eq!(
+            registry.parser_for_extension("md").unwrap().name(),
+            "markdown"
+        );
+    }
+
+    #[test]
+    fn extensionless_files_are_parsed_by_sniffing() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().join("README");
+        fs::write(&path, "<html><title>Readme</title><p>hello</p></html>").unwrap();
+
+        let registry = ParserRegistry::default();
+        let document = registry.parse_file(&path).unwrap();
+
+        assert_eq!(registry.parser_for_path(&path).unwrap().name(), "html");
+        assert_eq!(document.title.as_deref(), Some("Readme"));
+        assert_eq!(document.text, "hello");
+    }
+}
@@ -1,7 +1,14 @@
+use anyhow::{self, Context};
 use quick_xml::events::Event;
 use quick_xml::reader::Reader;
 
-pub fn parse_xml_string(input: String) -> String {
+use crate::parser_registry::DocumentParser;
+
+mod pdf;
+
+pub use pdf::parse_pdf_bytes;
+
+pub fn parse_xml_string(input: String) -> Result<String, anyhow::Error> {
     let mut reader = Reader::from_str(&input);
     reader.config_mut().trim_text(true);
     let mut buffer = Vec::new();
@@ -9,12 +16,587 @@ pub fn parse_xml_string(input: String) -> String {
     loop {
         let event = reader
             .read_event_into(&mut buffer)
-            .expect("Failed to read XML");
+            .context("Failed to read XML")?;
         match event {
             Event::Eof => break,
-            Event::Text(e) => txt.push_str(&e.decode().unwrap()),
+            Event::Text(e) => txt.push_str(&e.decode().context("Failed to decode XML text")?),
             _ => (),
         }
     }
-    txt
+    Ok(txt)
+}
+
+/// Text extracted from a document, with the title kept apart from the body when the
+/// format has one.
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedDocument {
+    pub title: Option<String>,
+    pub text: String,
+}
+
+/// Leading bytes with any byte order mark and whitespace removed.
+fn trim_leading_bytes(content: &[u8]) -> &[u8] {
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+    let start = content
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(content.len());
+    &content[start..]
+}
+
+fn starts_with_bytes_ignore_case(content: &[u8], prefix: &[u8]) -> bool {
+    content.len() >= prefix.len() && content[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+pub struct XmlParser;
+
+impl DocumentParser for XmlParser {
+    fn name(&self) -> &str {
+        "xml"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["xml"]
+    }
+
+    fn sniff(&self, content: &[u8]) -> bool {
+        trim_leading_bytes(content).starts_with(b"<?xml")
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+        Ok(ParsedDocument {
+            title: None,
+            text: parse_xml_string(String::from_utf8_lossy(content).into_owned())?,
+        })
+    }
+}
+
+pub struct HtmlParser;
+
+impl DocumentParser for HtmlParser {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["html", "htm"]
+    }
+
+    fn sniff(&self, content: &[u8]) -> bool {
+        let content = trim_leading_bytes(content);
+        starts_with_bytes_ignore_case(content, b"<!doctype html")
+            || starts_with_bytes_ignore_case(content, b"<html")
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+        Ok(parse_html_string(&String::from_utf8_lossy(content)))
+    }
+}
+
+pub struct MarkdownParser;
+
+impl DocumentParser for MarkdownParser {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["md", "markdown"]
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+        Ok(parse_markdown_string(&String::from_utf8_lossy(content)))
+    }
+}
+
+pub struct TextParser;
+
+impl DocumentParser for TextParser {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+
+    /// Anything that decodes as UTF-8 without control characters is considered text.
+    fn sniff(&self, content: &[u8]) -> bool {
+        // the sample may end in the middle of a multi-byte character
+        let valid = match std::str::from_utf8(content) {
+            Ok(text) => text,
+            Err(err) if err.error_len().is_none() => {
+                std::str::from_utf8(&content[..err.valid_up_to()]).unwrap_or_default()
+            }
+            Err(_) => return false,
+        };
+        !valid.is_empty() && !valid.chars().any(|c| c.is_control() && !c.is_whitespace())
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+        Ok(parse_text_string(&String::from_utf8_lossy(content)))
+    }
+}
+
+pub struct PdfParser;
+
+impl DocumentParser for PdfParser {
+    fn name(&self) -> &str {
+        "pdf"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pdf"]
+    }
+
+    fn sniff(&self, content: &[u8]) -> bool {
+        trim_leading_bytes(content).starts_with(b"%PDF-")
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+        parse_pdf_bytes(content)
+    }
+}
+
+/// Elements whose content is never shown to the reader.
+const HTML_SKIPPED_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// Elements rendered inline, which must not split the surrounding words.
+const HTML_INLINE_ELEMENTS: [&str; 15] = [
+    "a", "abbr", "b", "bdi", "cite", "code", "em", "i", "mark", "q", "s", "small", "span",
+    "strong", "u",
+];
+
+/// Extracts the visible text of an HTML document. Malformed markup is tolerated: unclosed
+/// tags, stray `<` characters and unknown entities are kept as plain text.
+pub fn parse_html_string(input: &str) -> ParsedDocument {
+    let chars: Vec<char> = input.chars().collect();
+    let mut document = ParsedDocument::default();
+    let mut title: Option<String> = None;
+    let mut raw_text = String::new();
+    // no tag can end past the last `>`, which keeps unterminated tags linear
+    let last_bracket = chars.iter().rposition(|c| *c == '>');
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '<' {
+            let text = title.as_mut().unwrap_or(&mut raw_text);
+            text.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let rest = &chars[i + 1..];
+        if starts_with_ignore_case(rest, "!--") {
+            i = find_sequence(&chars, i + 4, "-->").map_or(chars.len(), |end| end + 3);
+            continue;
+        }
+        if rest.first().is_some_and(|c| *c == '!' || *c == '?') {
+            i = find_tag_end(&chars, i + 1).map_or(chars.len(), |end| end + 1);
+            continue;
+        }
+
+        let is_closing = rest.first() == Some(&'/');
+        let name_start = if is_closing { i + 2 } else { i + 1 };
+        let name: String = chars[name_start..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let tag_end = if name.is_empty() || !chars[name_start].is_ascii_alphabetic() {
+            None
+        } else {
+            last_bracket
+                .filter(|last| *last > name_start)
+                .and_then(|_| find_tag_end(&chars, name_start))
+        };
+        let Some(tag_end) = tag_end else {
+            // not a tag, e.g. "a < b", or a tag left unterminated, keep the rest as text
+            title.as_mut().unwrap_or(&mut raw_text).push('<');
+            i += 1;
+            continue;
+        };
+        let is_self_closing = chars[tag_end - 1] == '/';
+        i = tag_end + 1;
+
+        if !is_closing && !is_self_closing && HTML_SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            i = find_closing_tag(&chars, i, &name).unwrap_or(chars.len());
+            continue;
+        }
+
+        if name == "title" {
+            if is_closing {
+                if let Some(title) = title.take() {
+                    let title = normalize_whitespace(&decode_html_entities(&title));
+                    if !title.is_empty() && document.title.is_none() {
+                        document.title = Some(title);
+                    }
+                }
+            } else if !is_self_closing {
+                title = Some(String::new());
+            }
+            continue;
+        }
+
+        if !HTML_INLINE_ELEMENTS.contains(&name.as_str()) {
+            title.as_mut().unwrap_or(&mut raw_text).push(' ');
+        }
+    }
+
+    // an unclosed title swallows the rest of the document, treat it as body text
+    if let Some(title) = title {
+        raw_text.push_str(&title);
+    }
+
+    document.text = normalize_whitespace(&decode_html_entities(&raw_text));
+    document
+}
+
+fn starts_with_ignore_case(chars: &[char], pattern: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    chars.len() >= pattern.len()
+        && chars
+            .iter()
+            .zip(&pattern)
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+fn find_sequence(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+    (from..chars.len()).find(|&i| starts_with_ignore_case(&chars[i..], pattern))
+}
+
+/// Position of the `>` closing the tag, skipping over quoted attribute values.
+fn find_tag_end(chars: &[char], from: usize) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (offset, c) in chars[from..].iter().enumerate() {
+        match quote {
+            Some(q) if *c == q => quote = None,
+            Some(_) => (),
+            None if *c == '"' || *c == '\'' => quote = Some(*c),
+            None if *c == '>' => return Some(from + offset),
+            None => (),
+        }
+    }
+    None
+}
+
+/// Position right after the closing tag of a raw text element such as `script`.
+fn find_closing_tag(chars: &[char], from: usize, name: &str) -> Option<usize> {
+    let closing = format!("</{name}");
+    let start = find_sequence(chars, from, &closing)?;
+    find_tag_end(chars, start + closing.len()).map(|end| end + 1)
+}
+
+fn normalize_whitespace(input: &str) -> String {
+    input.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn named_html_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "deg" => '°',
+        "euro" => '€',
+        "agrave" => 'à',
+        "aacute" => 'á',
+        "egrave" => 'è',
+        "eacute" => 'é',
+        "igrave" => 'ì',
+        "iacute" => 'í',
+        "ograve" => 'ò',
+        "oacute" => 'ó',
+        "ugrave" => 'ù',
+        "uacute" => 'ú',
+        "auml" => 'ä',
+        "ouml" => 'ö',
+        "uuml" => 'ü',
+        "szlig" => 'ß',
+        "ccedil" => 'ç',
+        "ntilde" => 'ñ',
+        _ => return None,
+    };
+    Some(c)
+}
+
+fn decode_html_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    named_html_entity(entity)
+}
+
+/// Decodes named and numeric character references, leaving unknown ones untouched.
+pub fn decode_html_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end > 0 && end <= 32)
+            .and_then(|end| decode_html_entity(&rest[1..=end]).map(|c| (c, end + 2)));
+        match decoded {
+            Some((c, consumed)) => {
+                output.push(c);
+                rest = &rest[consumed..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Plain text is indexed verbatim.
+pub fn parse_text_string(input: &str) -> ParsedDocument {
+    ParsedDocument {
+        title: None,
+        text: input.to_string(),
+    }
+}
+
+/// Extracts the prose of a Markdown document. Markup such as heading markers, emphasis,
+/// link targets and code fence delimiters is dropped, while heading text is kept. The first
+/// top level heading becomes the title.
+pub fn parse_markdown_string(input: &str) -> ParsedDocument {
+    let mut document = ParsedDocument::default();
+    let mut lines = Vec::new();
+    let mut fence: Option<&str> = None;
+
+    for line in input.lines() {
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            } else {
+                lines.push(line.to_string());
+            }
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = Some(marker);
+            continue;
+        }
+
+        if is_markdown_rule(trimmed) || is_markdown_link_definition(trimmed) {
+            continue;
+        }
+
+        let heading_level = trimmed.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&heading_level)
+            && trimmed[heading_level..].starts_with(char::is_whitespace)
+        {
+            let heading = strip_markdown_inline(trimmed[heading_level..].trim_end_matches('#'));
+            // the title is indexed on its own, like the HTML title
+            if heading_level == 1 && document.title.is_none() && !heading.is_empty() {
+                document.title = Some(heading);
+            } else {
+                lines.push(heading);
+            }
+            continue;
+        }
+
+        lines.push(strip_markdown_inline(strip_markdown_line_prefix(trimmed)));
+    }
+
+    document.text = lines.join("\n");
+    document
+}
+
+/// Thematic breaks and setext heading underlines.
+fn is_markdown_rule(line: &str) -> bool {
+    let mut markers = line.chars().filter(|c| !c.is_whitespace());
+    let Some(first) = markers.next() else {
+        return false;
+    };
+    let rest: Vec<char> = markers.collect();
+    ['-', '=', '*', '_'].contains(&first) && rest.len() >= 2 && rest.iter().all(|c| *c == first)
+}
+
+/// Reference style link definitions, e.g. `[docs]: https://example.com`.
+fn is_markdown_link_definition(line: &str) -> bool {
+    line.starts_with('[') && line.find("]:").is_some_and(|end| end > 1)
+}
+
+/// Removes blockquote and list markers.
+fn strip_markdown_line_prefix(mut line: &str) -> &str {
+    while let Some(rest) = line.strip_prefix('>') {
+        line = rest.trim_start();
+    }
+    for marker in ["- [ ] ", "- [x] ", "- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return rest;
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0
+        && let Some(rest) = line[digits..]
+            .strip_prefix(". ")
+            .or_else(|| line[digits..].strip_prefix(") "))
+    {
+        return rest;
+    }
+    line
+}
+
+/// Keeps link and image text while dropping their targets, autolinks and emphasis markers.
+fn strip_markdown_inline(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut output = String::with_capacity(line.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '!' if chars.get(i + 1) == Some(&'[') => i += 1,
+            ']' if chars.get(i + 1) == Some(&'(') => {
+                // skip the link target up to the matching parenthesis
+                let mut depth = 0;
+                i += 1;
+                while i < chars.len() {
+                    match chars[i] {
+                        '(' => depth += 1,
+                        ')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => (),
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            '<' if chars[i + 1..]
+                .iter()
+                .position(|c| *c == '>')
+                .is_some_and(|end| {
+                    let inner: String = chars[i + 1..i + 1 + end].iter().collect();
+                    inner.contains("://") || inner.starts_with("mailto:")
+                }) =>
+            {
+                i += chars[i..].iter().position(|c| *c == '>').unwrap_or(0) + 1;
+            }
+            '[' | ']' | '*' | '`' | '|' => {
+                output.push(' ');
+                i += 1;
+            }
+            '~' if chars.get(i + 1) == Some(&'~') => i += 2,
+            c => {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+    normalize_whitespace(&output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{decode_html_entities, parse_html_string, parse_markdown_string};
+
+    #[test]
+    fn html_title_is_extracted_separately() {
+        let html =
+            "<html><head><title>Ancient  Rome</title></head><body><p>The empire</p></body></html>";
+
+        let document = parse_html_string(html);
+
+        assert_eq!(document.title.as_deref(), Some("Ancient Rome"));
+        assert_eq!(document.text, "The empire");
+    }
+
+    #[test]
+    fn html_script_and_style_are_skipped() {
+        let html = "<style>p { color: red; }</style><p>visible</p><script>var x = '</p>';</script>";
+
+        let document = parse_html_string(html);
+
+        assert_eq!(document.text, "visible");
+    }
+
+    #[test]
+    fn html_tolerates_malformed_markup() {
+        let html = "<p>a < b<br><div class='x>y'>unclosed <b>bold<!-- hidden -->";
+
+        let document = parse_html_string(html);
+
+        assert_eq!(document.title, None);
+        assert_eq!(document.text, "a < b unclosed bold");
+    }
+
+    #[test]
+    fn html_unterminated_tag_keeps_the_following_text() {
+        let document = parse_html_string("<p>rome <a href=x>empire</a> <b roman republic");
+
+        assert_eq!(document.text, "rome empire <b roman republic");
+        let document = parse_html_string("<title>Rome</title>the <em eternal city");
+        assert_eq!(document.title.as_deref(), Some("Rome"));
+        assert_eq!(document.text, "the <em eternal city");
+    }
+
+    #[test]
+    fn html_entities_are_decoded() {
+        let decoded = decode_html_entities("caf&eacute; &amp; &#65;&#x42; &unknown; & done");
+
+        assert_eq!(decoded, "café & AB &unknown; & done");
+    }
+
+    #[test]
+    fn markdown_heading_text_is_kept_without_markers() {
+        let markdown = "# Trustami\n\nSome intro.\n\n## Usage ##\n# Second title\nSetext\n======\n";
+
+        let document = parse_markdown_string(markdown);
+
+        assert_eq!(document.title.as_deref(), Some("Trustami"));
+        assert_eq!(
+            document.text,
+            "\nSome intro.\n\nUsage\nSecond title\nSetext"
+        );
+    }
+
+    #[test]
+    fn markdown_links_keep_text_but_drop_urls() {
+        let markdown = "See [the docs](https://example.com/a_(b)) and ![logo](img.png) <https://x.y>\n\n[ref]: https://example.com";
+
+        let document = parse_markdown_string(markdown);
+
+        assert_eq!(document.text, "See the docs and logo\n");
+    }
+
+    #[test]
+    fn markdown_code_fences_and_emphasis_are_dropped() {
+        let markdown = "- **bold** and `code`\n```rust\nlet x = 1;\n```\n> quoted ~~text~~";
+
+        let document = parse_markdown_string(markdown);
+
+        assert_eq!(document.text, "bold and code\nlet x = 1;\nquoted text");
+    }
 }
@@ -0,0 +1,501 @@
+use anyhow::{self, Context, bail};
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+use crate::parsers::ParsedDocument;
+
+/// Most bytes a single stream may inflate to, tiny compressed streams can otherwise expand
+/// to gigabytes.
+const MAX_INFLATED_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// Dictionary names marking streams that never hold page text.
+const NON_TEXT_STREAM_NAMES: [&str; 8] = [
+    "Image",
+    "XRef",
+    "ObjStm",
+    "Metadata",
+    "Length1",
+    "Length2",
+    "Type1C",
+    "CIDFontType0C",
+];
+
+/// Extracts the text shown by the content streams of a PDF document. Uncompressed and
+/// Flate-compressed streams are supported, streams using other filters are ignored.
+pub fn parse_pdf_bytes(input: &[u8]) -> Result<ParsedDocument, anyhow::Error> {
+    let header_search = &input[..input.len().min(1024)];
+    if find_bytes(header_search, b"%PDF-", 0).is_none() {
+        bail!("Missing PDF header.");
+    }
+
+    let mut text = String::new();
+    let mut decoded_streams = 0;
+    let mut position = 0;
+
+    while let Some(keyword) = find_bytes(input, b"stream", position) {
+        position = keyword + b"stream".len();
+        if input[..keyword].ends_with(b"end") {
+            continue;
+        }
+
+        let data_start = skip_end_of_line(input, position);
+        let data_end = find_bytes(input, b"endstream", data_start).unwrap_or(input.len());
+        position = data_end;
+
+        let object_start = input[..keyword]
+            .windows(3)
+            .rposition(|window| window == b"obj")
+            .unwrap_or(0);
+        let names = dictionary_names(&input[object_start..keyword]);
+        if names
+            .iter()
+            .any(|name| NON_TEXT_STREAM_NAMES.contains(&name.as_str()))
+        {
+            continue;
+        }
+
+        let data = &input[data_start..data_end];
+        let filters: Vec<&String> = names.iter().filter(|n| n.ends_with("Decode")).collect();
+        let content = match filters.as_slice() {
+            [] => data.to_vec(),
+            [filter] if filter.as_str() == "FlateDecode" => {
+                match inflate(data, MAX_INFLATED_LENGTH) {
+                    Ok(content) => content,
+                    Err(_) => continue,
+                }
+            }
+            _ => continue,
+        };
+        decoded_streams += 1;
+
+        let stream_text = extract_content_text(&content);
+        if !stream_text.trim().is_empty() {
+            text.push_str(&stream_text);
+            text.push('\n');
+        }
+    }
+
+    if decoded_streams == 0 {
+        bail!("No decodable content stream was found.");
+    }
+
+    Ok(ParsedDocument {
+        title: extract_title(input),
+        text,
+    })
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from >= haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|offset| from + offset)
+}
+
+fn skip_end_of_line(input: &[u8], mut position: usize) -> usize {
+    if input.get(position) == Some(&b'\r') {
+        position += 1;
+    }
+    if input.get(position) == Some(&b'\n') {
+        position += 1;
+    }
+    position
+}
+
+fn inflate(data: &[u8], max_length: u64) -> Result<Vec<u8>, anyhow::Error> {
+    let mut content = Vec::new();
+    // one byte past the limit tells a stream of exactly the limit from a larger one
+    ZlibDecoder::new(data)
+        .take(max_length + 1)
+        .read_to_end(&mut content)
+        .context("Failed to inflate PDF stream.")?;
+    if content.len() as u64 > max_length {
+        bail!("PDF stream inflates to more than {max_length} bytes.");
+    }
+    Ok(content)
+}
+
+/// Every `/Name` appearing in a stream dictionary.
+fn dictionary_names(dictionary: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut lexer = Lexer::new(dictionary);
+    while let Some(token) = lexer.next_token() {
+        if let Token::Name(name) = token {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn extract_title(input: &[u8]) -> Option<String> {
+    let start = find_bytes(input, b"/Title", 0)? + b"/Title".len();
+    match Lexer::new(&input[start..]).next_token()? {
+        Token::String(bytes) => {
+            let title = decode_pdf_string(&bytes);
+            let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!title.is_empty()).then_some(title)
+        }
+        _ => None,
+    }
+}
+
+/// Runs the text showing operators of a content stream.
+fn extract_content_text(content: &[u8]) -> String {
+    let mut text = String::new();
+    let mut operands: Vec<Token> = Vec::new();
+    let mut lexer = Lexer::new(content);
+
+    while let Some(token) = lexer.next_token() {
+        let Token::Operator(operator) = token else {
+            operands.push(token);
+            continue;
+        };
+
+        match operator.as_str() {
+            "Tj" => push_last_string(&mut text, &operands),
+            "'" | "\"" => {
+                text.push('\n');
+                push_last_string(&mut text, &operands);
+            }
+            "TJ" => {
+                for operand in &operands {
+                    match operand {
+                        Token::String(bytes) => text.push_str(&decode_pdf_string(bytes)),
+                        // large negative adjustments are used as word spacing
+                        Token::Number(adjustment) if *adjustment < -200.0 => text.push(' '),
+                        _ => (),
+                    }
+                }
+            }
+            "Td" | "TD" | "Tm" => text.push(' '),
+            "T*" | "ET" => text.push('\n'),
+            "ID" => lexer.skip_inline_image(),
+            _ => (),
+        }
+        operands.clear();
+    }
+    text
+}
+
+fn push_last_string(text: &mut String, operands: &[Token]) {
+    if let Some(Token::String(bytes)) = operands
+        .iter()
+        .rev()
+        .find(|operand| matches!(operand, Token::String(_)))
+    {
+        text.push_str(&decode_pdf_string(bytes));
+    }
+}
+
+/// Strings with a byte order mark are UTF-16, anything else is read as Latin-1.
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    String(Vec<u8>),
+    Name(String),
+    Operator(String),
+    ArrayStart,
+    ArrayEnd,
+    DictionaryStart,
+    DictionaryEnd,
+}
+
+struct Lexer<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn chop_while<F>(&mut self, mut predicate: F) -> &'a [u8]
+    where
+        F: FnMut(u8) -> bool,
+    {
+        let start = self.position;
+        while self.peek().is_some_and(&mut predicate) {
+            self.position += 1;
+        }
+        &self.input[start..self.position]
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            self.chop_while(|byte| byte.is_ascii_whitespace() || byte == 0);
+            if self.peek() == Some(b'%') {
+                self.chop_while(|byte| byte != b'\n' && byte != b'\r');
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Inline image data is binary and ends at the first `EI` operator.
+    fn skip_inline_image(&mut self) {
+        while self.position + 2 <= self.input.len() {
+            let ends_image = &self.input[self.position..self.position + 2] == b"EI"
+                && self.input[self.position - 1].is_ascii_whitespace()
+                && self
+                    .input
+                    .get(self.position + 2)
+                    .is_none_or(|byte| byte.is_ascii_whitespace());
+            self.position += 1;
+            if ends_image {
+                self.position += 1;
+                return;
+            }
+        }
+        self.position = self.input.len();
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_whitespace_and_comments();
+        let byte = self.peek()?;
+
+        let token = match byte {
+            b'(' => {
+                self.position += 1;
+                Token::String(self.literal_string())
+            }
+            b'<' if self.input.get(self.position + 1) == Some(&b'<') => {
+                self.position += 2;
+                Token::DictionaryStart
+            }
+            b'>' if self.input.get(self.position + 1) == Some(&b'>') => {
+                self.position += 2;
+                Token::DictionaryEnd
+            }
+            b'<' => {
+                self.position += 1;
+                let digits = self.chop_while(|byte| byte != b'>');
+                // a truncated file may end before the closing bracket
+                if self.peek() == Some(b'>') {
+                    self.position += 1;
+                }
+                Token::String(decode_hex_string(digits))
+            }
+            b'[' => {
+                self.position += 1;
+                Token::ArrayStart
+            }
+            b']' => {
+                self.position += 1;
+                Token::ArrayEnd
+            }
+            b'/' => {
+                self.position += 1;
+                let name =
+                    self.chop_while(|byte| !byte.is_ascii_whitespace() && !is_delimiter(byte));
+                Token::Name(String::from_utf8_lossy(name).into_owned())
+            }
+            b'0'..=b'9' | b'+' | b'-' | b'.' => {
+                let number = self
+                    .chop_while(|byte| byte.is_ascii_digit() || matches!(byte, b'+' | b'-' | b'.'));
+                let number = std::str::from_utf8(number).ok()?;
+                Token::Number(number.parse().unwrap_or_default())
+            }
+            _ if is_delimiter(byte) => {
+                // unbalanced delimiters such as a stray ')' carry no text
+                self.position += 1;
+                Token::Operator(String::new())
+            }
+            _ => {
+                let operator =
+                    self.chop_while(|byte| !byte.is_ascii_whitespace() && !is_delimiter(byte));
+                Token::Operator(String::from_utf8_lossy(operator).into_owned())
+            }
+        };
+        Some(token)
+    }
+
+    /// Reads a literal string after its opening parenthesis, resolving escapes.
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut depth = 1;
+
+        while let Some(byte) = self.peek() {
+            self.position += 1;
+            match byte {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        break;
+                    };
+                    self.position += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0C),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + (digit - b'0') as u32;
+                                        self.position += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        // a backslash at the end of a line continues the string
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.position += 1;
+                            }
+                        }
+                        b'\n' => (),
+                        other => bytes.push(other),
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+            bytes.push(byte);
+        }
+        bytes
+    }
+}
+
+fn decode_hex_string(digits: &[u8]) -> Vec<u8> {
+    let mut digits: Vec<u8> = digits
+        .iter()
+        .filter_map(|digit| (*digit as char).to_digit(16).map(|value| value as u8))
+        .collect();
+    if digits.len() % 2 == 1 {
+        digits.push(0);
+    }
+    digits
+        .chunks_exact(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    use crate::parsers::pdf::{extract_title, inflate, parse_pdf_bytes};
+
+    fn create_pdf(content: &[u8], compressed: bool) -> Vec<u8> {
+        let (data, filter) = if compressed {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content).unwrap();
+            (encoder.finish().unwrap(), " /Filter /FlateDecode")
+        } else {
+            (content.to_vec(), "")
+        };
+
+        let mut pdf = b"%PDF-1.4\n1 0 obj\n<< /Title (Roman \\(history\\)) >>\nendobj\n".to_vec();
+        pdf.extend(format!("4 0 obj\n<< /Length {}{} >>\nstream\n", data.len(), filter).as_bytes());
+        pdf.extend(&data);
+        pdf.extend(b"\nendstream\nendobj\ntrailer\n<< /Info 1 0 R >>\n%%EOF\n");
+        pdf
+    }
+
+    const CONTENT: &[u8] =
+        b"BT /F1 12 Tf 72 712 Td (Ancient Rome) Tj 0 -14 Td [(The) -250 (em) 10 (pire)] TJ ET\nBT <4974616c79> Tj ET";
+
+    #[test]
+    fn pdf_uncompressed_stream_text_is_extracted() {
+        let document = parse_pdf_bytes(&create_pdf(CONTENT, false)).unwrap();
+
+        assert_eq!(document.title.as_deref(), Some("Roman (history)"));
+        assert_eq!(
+            document.text.split_whitespace().collect::<Vec<_>>(),
+            ["Ancient", "Rome", "The", "empire", "Italy"]
+        );
+    }
+
+    #[test]
+    fn pdf_flate_stream_text_is_extracted() {
+        let document = parse_pdf_bytes(&create_pdf(CONTENT, true)).unwrap();
+
+        assert_eq!(
+            document.text.split_whitespace().collect::<Vec<_>>(),
+            ["Ancient", "Rome", "The", "empire", "Italy"]
+        );
+    }
+
+    #[test]
+    fn pdf_literal_string_escapes_are_resolved() {
+        let document =
+            parse_pdf_bytes(&create_pdf(b"BT (caf\\351 \\(x\\)\\\nz) Tj ET", false)).unwrap();
+
+        assert_eq!(document.text.trim(), "café (x)z");
+    }
+
+    #[test]
+    fn invalid_pdf_is_an_error() {
+        assert!(parse_pdf_bytes(b"").is_err());
+        assert!(parse_pdf_bytes(b"just some text").is_err());
+    }
+
+    #[test]
+    fn inflated_streams_are_capped() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[b'a'; 1000]).unwrap();
+        let data = encoder.finish().unwrap();
+
+        assert_eq!(inflate(&data, 1000).unwrap().len(), 1000);
+        assert!(inflate(&data, 999).is_err());
+    }
+
+    #[test]
+    fn unterminated_hex_strings_do_not_panic() {
+        let document = parse_pdf_bytes(&create_pdf(b"BT <48656c", false)).unwrap();
+        assert_eq!(document.title.as_deref(), Some("Roman (history)"));
+
+        let truncated = b"%PDF-1.4\n1 0 obj\n<< /Title <526f6d65";
+        assert_eq!(extract_title(truncated).as_deref(), Some("Rome"));
+        assert!(parse_pdf_bytes(truncated).is_err());
+    }
+
+    #[test]
+    fn unsupported_filters_are_skipped() {
+        let mut pdf = b"%PDF-1.4\n1 0 obj\n<< /Length 3 /Filter /DCTDecode >>\nstream\nabc\nendstream\nendobj\n".to_vec();
+        pdf.extend(create_pdf(b"BT (kept) Tj ET", false));
+
+        let document = parse_pdf_bytes(&pdf).unwrap();
+
+        assert_eq!(document.text.trim(), "kept");
+    }
+}
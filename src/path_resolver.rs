@@ -1,46 +1,500 @@
 use anyhow;
 use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::gitignore::{self, Gitignore};
+use crate::glob::Glob;
+use crate::parser_registry::ParserRegistry;
+
+/// Which files of a directory tree get indexed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TraversalOptions {
+    /// When not empty, only files matching at least one pattern are collected
+    pub include: Vec<Glob>,
+    /// Files and directories matching any pattern are skipped
+    pub exclude: Vec<Glob>,
+    /// Depth of the deepest files collected, files directly inside the root are at depth 1
+    pub max_depth: Option<usize>,
+    /// Skip paths listed in `.gitignore`, `.ignore` and `.trustamiignore` files
+    pub respect_ignore_files: bool,
+}
+
+impl Default for TraversalOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            respect_ignore_files: true,
+        }
+    }
+}
+
+impl TraversalOptions {
+    fn is_excluded(&self, relative_path: &str) -> bool {
+        self.exclude.iter().any(|glob| glob.is_match(relative_path))
+    }
+
+    fn is_included(&self, relative_path: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(relative_path))
+    }
+}
 
 fn get_extension_from_filename(filename: &str) -> Option<&str> {
     Path::new(filename).extension().and_then(OsStr::to_str)
 }
 
-pub fn collect_valid_paths<P>(data_dir_path: P) -> Result<Vec<PathBuf>, anyhow::Error>
-where
-    P: AsRef<Path>,
-{
-    let mut file_paths = Vec::new();
-    let data_dir = fs::read_dir(data_dir_path).context("Failed to find specified directory.")?;
-
-    for element in data_dir {
-        let dir_entry = element.unwrap();
-        let path = dir_entry.path();
-        let file_type = dir_entry
-            .file_type()
-            .with_context(|| format!("Could not retrieve file type for {}", { path.display() }))?;
-
-        if file_type.is_file() {
-            let filename = dir_entry
-                .file_name()
-                .to_str()
-                .context("Failed to convert filename to string")?
-                //.expect("Failed to convert filename")
-                .to_owned();
-            if let Some(extension) = get_extension_from_filename(&filename) {
-                if extension == "xml" {
-                    println!("Obtained file extension for: {}", filename);
-                    file_paths.push(path);
-                } else {
-                    eprintln!("File extension for: {} is not supported.", filename);
+/// Path relative to the traversal root, always using `/` as separator.
+fn relative_path_string(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+struct PendingDirectory {
+    path: PathBuf,
+    depth: usize,
+    ignore_stack: Vec<Rc<Gitignore>>,
+}
+
+/// Depth-first traversal shared by full and partial collections.
+struct Walker<'a> {
+    root: &'a Path,
+    parser_registry: &'a ParserRegistry,
+    options: &'a TraversalOptions,
+    visited: HashSet<PathBuf>,
+    pending: Vec<PendingDirectory>,
+    file_paths: Vec<PathBuf>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(
+        root: &'a Path,
+        parser_registry: &'a ParserRegistry,
+        options: &'a TraversalOptions,
+    ) -> Result<Self, anyhow::Error> {
+        let canonical_root = root
+            .canonicalize()
+            .context("Failed to find specified directory.")?;
+        Ok(Self {
+            root,
+            parser_registry,
+            options,
+            visited: HashSet::from([canonical_root]),
+            pending: Vec::new(),
+            file_paths: Vec::new(),
+        })
+    }
+
+    fn load_ignore_files(&self, directory: &Path, ignore_stack: &mut Vec<Rc<Gitignore>>) {
+        if self.options.respect_ignore_files
+            && let Some(gitignore) = Gitignore::from_directory(directory)
+        {
+            ignore_stack.push(Rc::new(gitignore));
+        }
+    }
+
+    fn is_skipped_directory(
+        &self,
+        path: &Path,
+        depth: usize,
+        ignore_stack: &[Rc<Gitignore>],
+    ) -> bool {
+        self.options
+            .is_excluded(&relative_path_string(self.root, path))
+            || (self.options.respect_ignore_files
+                && (path.file_name() == Some(OsStr::new(".git"))
+                    || gitignore::is_ignored(ignore_stack.iter().map(Rc::as_ref), path, true)))
+            || self.options.max_depth.is_some_and(|max| depth + 1 >= max)
+    }
+
+    /// Handles one entry of a directory at `depth`, queueing subdirectories and
+    /// collecting supported files.
+    fn visit(
+        &mut self,
+        path: PathBuf,
+        depth: usize,
+        ignore_stack: &[Rc<Gitignore>],
+    ) -> Result<(), anyhow::Error> {
+        let relative_path = relative_path_string(self.root, &path);
+        if self.options.is_excluded(&relative_path) {
+            return Ok(());
+        }
+
+        // follows symbolic links
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                eprintln!(
+                    "Could not retrieve file type for {}: {}",
+                    path.display(),
+                    err
+                );
+                return Ok(());
+            }
+        };
+
+        if metadata.is_dir() {
+            if self.is_skipped_directory(&path, depth, ignore_stack) {
+                return Ok(());
+            }
+            match path.canonicalize() {
+                Ok(canonical) => {
+                    if self.visited.insert(canonical) {
+                        self.pending.push(PendingDirectory {
+                            path,
+                            depth: depth + 1,
+                            ignore_stack: ignore_stack.to_vec(),
+                        });
+                    } else {
+                        eprintln!("Skipping already visited directory {}.", relative_path);
+                    }
                 }
+                Err(err) => eprintln!("Could not resolve {}: {}", path.display(), err),
+            }
+            return Ok(());
+        }
+
+        if self.options.respect_ignore_files
+            && gitignore::is_ignored(ignore_stack.iter().map(Rc::as_ref), &path, false)
+        {
+            return Ok(());
+        }
+
+        let is_ignore_file = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .is_some_and(|filename| gitignore::IGNORE_FILENAMES.contains(&filename));
+        if !metadata.is_file() || is_ignore_file || !self.options.is_included(&relative_path) {
+            return Ok(());
+        }
+
+        let filename = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .context("Failed to convert filename to string")?;
+        if let Some(extension) = get_extension_from_filename(filename) {
+            if self
+                .parser_registry
+                .parser_for_extension(extension)
+                .is_some()
+            {
+                println!("Obtained file extension for: {}", relative_path);
+                self.file_paths.push(path);
             } else {
-                eprintln!("Could not obtain file extension for: {}.", filename);
+                eprintln!("File extension for: {} is not supported.", relative_path);
             }
+        } else if let Some(parser) = self.parser_registry.parser_for_path(&path) {
+            println!("Detected {} content for: {}", parser.name(), relative_path);
+            self.file_paths.push(path);
+        } else {
+            eprintln!("Could not detect the format of: {}.", relative_path);
+        }
+        Ok(())
+    }
+
+    fn walk(mut self) -> Result<Vec<PathBuf>, anyhow::Error> {
+        while let Some(PendingDirectory {
+            path: directory,
+            depth,
+            mut ignore_stack,
+        }) = self.pending.pop()
+        {
+            let data_dir = match fs::read_dir(&directory) {
+                Ok(data_dir) => data_dir,
+                Err(err) if directory == self.root => {
+                    return Err(err).context("Failed to find specified directory.");
+                }
+                Err(err) => {
+                    eprintln!("Could not read directory {}: {}", directory.display(), err);
+                    continue;
+                }
+            };
+            self.load_ignore_files(&directory, &mut ignore_stack);
+
+            let mut entries: Vec<PathBuf> = data_dir
+                .filter_map(|element| match element {
+                    Ok(dir_entry) => Some(dir_entry.path()),
+                    Err(err) => {
+                        eprintln!(
+                            "An error occured while reading {}: {}",
+                            directory.display(),
+                            err
+                        );
+                        None
+                    }
+                })
+                .collect();
+            entries.sort();
+
+            for path in entries {
+                self.visit(path, depth, &ignore_stack)?;
+            }
+        }
+        Ok(self.file_paths)
+    }
+}
+
+/// Recursively collects the files of `data_dir_path` that a registered parser supports.
+/// Symbolic links are followed, each directory is visited at most once so link cycles
+/// cannot cause infinite recursion. Unless disabled, ignore files apply to the directory
+/// they are in and everything below it, and `.git` directories are skipped.
+pub fn collect_valid_paths<P>(
+    data_dir_path: P,
+    parser_registry: &ParserRegistry,
+    options: &TraversalOptions,
+) -> Result<Vec<PathBuf>, anyhow::Error>
+where
+    P: AsRef<Path>,
+{
+    let root = data_dir_path.as_ref();
+    let mut walker = Walker::new(root, parser_registry, options)?;
+    walker.pending.push(PendingDirectory {
+        path: root.to_path_buf(),
+        depth: 0,
+        ignore_stack: Vec::new(),
+    });
+    walker.walk()
+}
+
+/// Collects the files below `scope`, a file or directory inside `data_dir_path`, that
+/// `collect_valid_paths` would collect for the whole tree. Returns nothing when `scope`
+/// no longer exists or lies outside the tree.
+pub fn collect_valid_paths_in<P, Q>(
+    data_dir_path: P,
+    scope: Q,
+    parser_registry: &ParserRegistry,
+    options: &TraversalOptions,
+) -> Result<Vec<PathBuf>, anyhow::Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let root = data_dir_path.as_ref();
+    let scope = scope.as_ref();
+    let Ok(relative) = scope.strip_prefix(root) else {
+        return Ok(Vec::new());
+    };
+    if relative.as_os_str().is_empty() {
+        return collect_valid_paths(root, parser_registry, options);
+    }
+    if !scope.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut walker = Walker::new(root, parser_registry, options)?;
+    let mut ignore_stack = Vec::new();
+    let mut directory = root.to_path_buf();
+    walker.load_ignore_files(&directory, &mut ignore_stack);
+
+    // the directories between the root and the scope must not be skipped themselves
+    let components: Vec<_> = relative.components().collect();
+    let (last, ancestors) = components.split_last().context("Scope has no file name.")?;
+    for (depth, component) in ancestors.iter().enumerate() {
+        directory.push(component);
+        if walker.is_skipped_directory(&directory, depth, &ignore_stack) {
+            return Ok(Vec::new());
         }
+        if let Ok(canonical) = directory.canonicalize() {
+            walker.visited.insert(canonical);
+        }
+        walker.load_ignore_files(&directory, &mut ignore_stack);
+    }
+
+    walker.visit(directory.join(last), ancestors.len(), &ignore_stack)?;
+    walker.walk()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::{TempDir, tempdir};
+
+    use crate::glob::Glob;
+    use crate::parser_registry::ParserRegistry;
+    use crate::path_resolver::{TraversalOptions, collect_valid_paths, collect_valid_paths_in};
+
+    fn create_tree() -> TempDir {
+        let temp = tempdir().unwrap();
+        for file in [
+            "top.md",
+            "skip.docx",
+            "docs/guide.md",
+            "docs/api/index.html",
+            "docs/api/notes.txt",
+            "target/build.txt",
+        ] {
+            let path = temp.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "content").unwrap();
+        }
+        temp
+    }
+
+    fn collect(root: &Path, options: &TraversalOptions) -> Vec<String> {
+        let mut paths: Vec<String> = collect_valid_paths(root, &ParserRegistry::default(), options)
+            .unwrap()
+            .iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn globs(patterns: &[&str]) -> Vec<Glob> {
+        patterns.iter().map(|p| Glob::new(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn nested_directories_are_collected() {
+        let temp = create_tree();
+
+        let paths = collect(temp.path(), &TraversalOptions::default());
+
+        assert_eq!(
+            paths,
+            [
+                "docs/api/index.html",
+                "docs/api/notes.txt",
+                "docs/guide.md",
+                "target/build.txt",
+                "top.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn include_and_exclude_globs_filter_paths() {
+        let temp = create_tree();
+        let options = TraversalOptions {
+            include: globs(&["*.md", "*.txt"]),
+            exclude: globs(&["target", "docs/api/*.txt"]),
+            ..Default::default()
+        };
+
+        let paths = collect(temp.path(), &options);
+
+        assert_eq!(paths, ["docs/guide.md", "top.md"]);
+    }
+
+    #[test]
+    fn max_depth_limits_recursion() {
+        let temp = create_tree();
+
+        let top_level = TraversalOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let two_levels = TraversalOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(collect(temp.path(), &top_level), ["top.md"]);
+        assert_eq!(
+            collect(temp.path(), &two_levels),
+            ["docs/guide.md", "target/build.txt", "top.md"]
+        );
+    }
+
+    #[test]
+    fn ignore_files_are_respected() {
+        let temp = create_tree();
+        fs::write(temp.path().join(".gitignore"), "target/\n*.txt\n").unwrap();
+        fs::write(temp.path().join("docs/.ignore"), "!notes.txt\n").unwrap();
+        fs::write(
+            temp.path().join("docs/api/.trustamiignore"),
+            "/index.html\n",
+        )
+        .unwrap();
+        fs::create_dir(temp.path().join(".git")).unwrap();
+        fs::write(temp.path().join(".git/HEAD.txt"), "ref").unwrap();
+
+        let paths = collect(temp.path(), &TraversalOptions::default());
+
+        assert_eq!(paths, ["docs/api/notes.txt", "docs/guide.md", "top.md"]);
+    }
+
+    #[test]
+    fn ignore_files_can_be_disabled() {
+        let temp = create_tree();
+        fs::write(temp.path().join(".gitignore"), "*\n").unwrap();
+        let options = TraversalOptions {
+            respect_ignore_files: false,
+            ..Default::default()
+        };
+
+        let paths = collect(temp.path(), &options);
+
+        assert_eq!(paths.len(), 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles_are_visited_once() {
+        let temp = create_tree();
+        std::os::unix::fs::symlink(temp.path(), temp.path().join("docs/loop")).unwrap();
+
+        let paths = collect(temp.path(), &TraversalOptions::default());
+
+        assert_eq!(paths.len(), 5);
+    }
+
+    #[test]
+    fn partial_collection_applies_the_same_rules() {
+        let temp = create_tree();
+        fs::write(temp.path().join("docs/.gitignore"), "notes.txt\n").unwrap();
+        let options = TraversalOptions {
+            exclude: globs(&["target"]),
+            ..Default::default()
+        };
+        let collect_in = |scope: &str| {
+            collect_valid_paths_in(
+                temp.path(),
+                temp.path().join(scope),
+                &ParserRegistry::default(),
+                &options,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            collect_in("docs/api"),
+            [temp.path().join("docs/api/index.html")]
+        );
+        assert_eq!(
+            collect_in("docs/guide.md"),
+            [temp.path().join("docs/guide.md")]
+        );
+        assert!(collect_in("docs/api/notes.txt").is_empty());
+        assert!(collect_in("target/build.txt").is_empty());
+        assert!(collect_in("docs/removed.md").is_empty());
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        let result = collect_valid_paths(
+            PathBuf::from("/definitely/not/here"),
+            &ParserRegistry::default(),
+            &TraversalOptions::default(),
+        );
+
+        assert!(result.is_err());
     }
-    Ok(file_paths)
 }
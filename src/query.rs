@@ -0,0 +1,1191 @@
+use anyhow;
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+use crate::inverted_index::DocumentId;
+use crate::utils::{IndexReader, tokenize_query};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum QueryParseError {
+    #[error("Phrase starting at character {0} is missing its closing quote")]
+    UnterminatedPhrase(usize),
+    #[error("Expected a number of tokens after `~` at character {0}")]
+    InvalidSlop(usize),
+    #[error("Expected a term, phrase or group at character {0}")]
+    ExpectedTerm(usize),
+    #[error("Parenthesis at character {0} is never closed")]
+    UnclosedGroup(usize),
+    #[error("Closing parenthesis at character {0} has no matching opening one")]
+    UnmatchedClose(usize),
+    #[error("Fuzzy term at character {0} allows more than {MAX_EDITS} edits")]
+    TooManyEdits(usize),
+}
+
+/// Number of terms a wildcard or fuzzy term expands to at most by default.
+pub const DEFAULT_MAX_EXPANSIONS: usize = 1024;
+/// Most edits a fuzzy term may allow, more would match a large part of the vocabulary.
+pub const MAX_EDITS: u32 = 2;
+
+/// Terms that must occur in this order, with at most `slop` other tokens between them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phrase {
+    pub terms: Vec<String>,
+    pub slop: u32,
+}
+
+/// A parsed query. Clauses separated by spaces may be prefixed by `+` to require them or
+/// by `-` to exclude them, `AND`, `OR` and `NOT` bind tighter than spaces, and
+/// parentheses group clauses. Quoted phrases may allow other tokens in between, as in
+/// `"machine learning"~3`, terms may contain the wildcards `*` and `?`, and `algoritm~1`
+/// matches the terms within one edit of algoritm.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    Phrase(Phrase),
+    /// Term pattern where `*` matches any run of characters and `?` a single one, replaced
+    /// by the matching terms with `expand` before searching
+    Wildcard(String),
+    /// Term matching the terms within `max_edits` insertions, deletions or substitutions,
+    /// replaced by them with `expand` before searching
+    Fuzzy {
+        term: String,
+        max_edits: u32,
+    },
+    /// Term whose contribution to the score is scaled by `weight`
+    Weighted {
+        term: String,
+        weight: f32,
+    },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    /// Documents must match every required clause, or at least one optional clause when
+    /// none is required, and no excluded clause
+    Clauses {
+        required: Vec<Query>,
+        optional: Vec<Query>,
+        excluded: Vec<Query>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(Phrase),
+    Fuzzy { term: String, max_edits: u32 },
+    And,
+    Or,
+    Not,
+    Required,
+    Excluded,
+    Open,
+    Close,
+}
+
+fn ends_word(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '"')
+}
+
+/// Splits a query string into tokens, each with the position of its first character.
+fn lex(chars: &[char]) -> Result<Vec<(usize, Token)>, QueryParseError> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < chars.len() {
+        let start = position;
+        match chars[position] {
+            c if c.is_whitespace() => position += 1,
+            '(' => {
+                tokens.push((start, Token::Open));
+                position += 1;
+            }
+            ')' => {
+                tokens.push((start, Token::Close));
+                position += 1;
+            }
+            '"' => {
+                let length = chars[start + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or(QueryParseError::UnterminatedPhrase(start))?;
+                let text: String = chars[start + 1..start + 1 + length].iter().collect();
+                position = start + length + 2;
+
+                let mut slop = 0;
+                if chars.get(position) == Some(&'~') {
+                    let digits: String = chars[position + 1..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_digit())
+                        .collect();
+                    slop = digits
+                        .parse()
+                        .map_err(|_| QueryParseError::InvalidSlop(position))?;
+                    position += 1 + digits.len();
+                }
+                tokens.push((
+                    start,
+                    Token::Phrase(Phrase {
+                        terms: tokenize_query(&text),
+                        slop,
+                    }),
+                ));
+            }
+            // a lone sign is searched for like any other character
+            sign @ ('+' | '-')
+                if chars
+                    .get(start + 1)
+                    .is_some_and(|c| !c.is_whitespace() && *c != ')') =>
+            {
+                let token = if sign == '+' {
+                    Token::Required
+                } else {
+                    Token::Excluded
+                };
+                tokens.push((start, token));
+                position += 1;
+            }
+            _ => {
+                while position < chars.len() && !ends_word(chars[position]) {
+                    position += 1;
+                }
+                let word: String = chars[start..position].iter().collect();
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    // other uses of `~`, e.g. `foo~bar`, are searched as plain words
+                    _ => match word.rsplit_once('~') {
+                        Some((term, edits))
+                            if !term.is_empty()
+                                && !edits.is_empty()
+                                && edits.chars().all(|c| c.is_ascii_digit()) =>
+                        {
+                            let tilde = start + term.chars().count();
+                            let max_edits = edits
+                                .parse()
+                                .ok()
+                                .filter(|max_edits| *max_edits <= MAX_EDITS)
+                                .ok_or(QueryParseError::TooManyEdits(tilde))?;
+                            Token::Fuzzy {
+                                term: term.to_string(),
+                                max_edits,
+                            }
+                        }
+                        _ => Token::Word(word),
+                    },
+                };
+                tokens.push((start, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Number of characters of the query, reported for errors at its end
+    length: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.length, |(position, _)| *position)
+    }
+
+    fn consume_if(&mut self, expected: &Token) -> bool {
+        let matches = self.peek() == Some(expected);
+        if matches {
+            self.next += 1;
+        }
+        matches
+    }
+
+    /// Parses clauses up to the end of the query, or up to the closing parenthesis of the
+    /// group opened at `group_start`.
+    fn parse_clauses(&mut self, group_start: Option<usize>) -> Result<Query, QueryParseError> {
+        let mut required = Vec::new();
+        let mut optional = Vec::new();
+        let mut excluded = Vec::new();
+        loop {
+            match (self.peek(), group_start) {
+                (None, None) => break,
+                (None, Some(group_start)) => {
+                    return Err(QueryParseError::UnclosedGroup(group_start));
+                }
+                (Some(Token::Close), None) => {
+                    return Err(QueryParseError::UnmatchedClose(self.position()));
+                }
+                (Some(Token::Close), Some(_)) => {
+                    self.next += 1;
+                    break;
+                }
+                _ => {}
+            }
+            if self.consume_if(&Token::Required) {
+                required.push(self.parse_or()?);
+            } else if self.consume_if(&Token::Excluded) {
+                excluded.push(self.parse_or()?);
+            } else {
+                optional.push(self.parse_or()?);
+            }
+        }
+
+        if required.is_empty() && excluded.is_empty() && optional.len() == 1 {
+            return Ok(optional.remove(0));
+        }
+        Ok(Query::Clauses {
+            required,
+            optional,
+            excluded,
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryParseError> {
+        let mut operands = vec![self.parse_and()?];
+        while self.consume_if(&Token::Or) {
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Query::Or(operands)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryParseError> {
+        let mut operands = vec![self.parse_not()?];
+        while self.consume_if(&Token::And) {
+            operands.push(self.parse_not()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Query::And(operands)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Query, QueryParseError> {
+        if self.consume_if(&Token::Not) {
+            return Ok(Query::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, QueryParseError> {
+        let position = self.position();
+        let query = match self.peek() {
+            Some(Token::Word(word)) => {
+                let term_query = |term: String| {
+                    if term.contains(['*', '?']) {
+                        Query::Wildcard(term)
+                    } else {
+                        Query::Term(term)
+                    }
+                };
+                let mut terms = tokenize_word(word);
+                match terms.len() {
+                    0 => return Err(QueryParseError::ExpectedTerm(position)),
+                    1 => term_query(terms.remove(0)),
+                    // words like e-mail are made of several tokens
+                    _ if !terms.iter().any(|term| term.contains(['*', '?'])) => {
+                        Query::Phrase(Phrase { terms, slop: 0 })
+                    }
+                    _ => Query::And(terms.into_iter().map(term_query).collect()),
+                }
+            }
+            Some(Token::Phrase(phrase)) => Query::Phrase(phrase.clone()),
+            Some(Token::Fuzzy { term, max_edits }) => Query::Fuzzy {
+                term: term.to_lowercase(),
+                max_edits: *max_edits,
+            },
+            Some(Token::Open) => {
+                self.next += 1;
+                return self.parse_clauses(Some(position));
+            }
+            _ => return Err(QueryParseError::ExpectedTerm(position)),
+        };
+        self.next += 1;
+        Ok(query)
+    }
+}
+
+/// Tokenizes a query word like document text, keeping the wildcards that extend a token:
+/// `*` inside or at the end of one and `?` inside one. Other wildcard characters are
+/// punctuation, as in `is rome?`, and separate tokens.
+fn tokenize_word(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let is_token_char = |i: usize| chars.get(i).is_some_and(|c| c.is_alphanumeric());
+    let mut extends_token = vec![false; chars.len()];
+    for (i, c) in chars.iter().enumerate() {
+        let follows_token = i > 0 && (is_token_char(i - 1) || extends_token[i - 1]);
+        extends_token[i] = match c {
+            '*' => follows_token,
+            '?' => follows_token && is_token_char(i + 1),
+            _ => false,
+        };
+    }
+
+    let mut tokens: Vec<String> = Vec::new();
+    let mut literal = String::new();
+    // whether the pending literal text directly follows a wildcard
+    let mut continues_token = false;
+    let flush = |tokens: &mut Vec<String>, literal: &mut String, continues_token: bool| {
+        let mut literal_tokens = tokenize_query(literal).into_iter();
+        if continues_token
+            && literal.starts_with(char::is_alphanumeric)
+            && let (Some(last), Some(first)) = (tokens.last_mut(), literal_tokens.next())
+        {
+            last.push_str(&first);
+        }
+        tokens.extend(literal_tokens);
+        literal.clear();
+    };
+    for (c, extends_token) in chars.iter().zip(extends_token) {
+        if extends_token {
+            flush(&mut tokens, &mut literal, continues_token);
+            if let Some(last) = tokens.last_mut() {
+                last.push(*c);
+            }
+            continues_token = true;
+        } else if matches!(c, '*' | '?') {
+            literal.push(' ');
+        } else {
+            literal.push(*c);
+        }
+    }
+    flush(&mut tokens, &mut literal, continues_token);
+    tokens
+}
+
+impl Query {
+    pub fn parse(query_string: &str) -> Result<Self, QueryParseError> {
+        let chars: Vec<char> = query_string.chars().collect();
+        let mut parser = Parser {
+            tokens: lex(&chars)?,
+            next: 0,
+            length: chars.len(),
+        };
+        parser.parse_clauses(None)
+    }
+
+    /// Ids of the documents matching the query, only the postings of its terms are read.
+    pub fn matching_documents<I>(&self, index: &I) -> Result<BTreeSet<DocumentId>, anyhow::Error>
+    where
+        I: IndexReader + ?Sized,
+    {
+        let all_documents = || (0..index.document_count() as DocumentId).collect();
+        Ok(match self {
+            Query::Term(term) | Query::Weighted { term, .. } => index
+                .postings(term)?
+                .iter()
+                .map(|posting| posting.document_id)
+                .collect(),
+            Query::Phrase(phrase) => phrase_matches(index, phrase)?
+                .into_iter()
+                .map(|(document_id, _)| document_id)
+                .collect(),
+            Query::Wildcard(_) | Query::Fuzzy { .. } => {
+                anyhow::bail!("Wildcards and fuzzy terms must be expanded before searching")
+            }
+            Query::And(operands) => intersection(index, operands)?,
+            Query::Or(operands) => union(index, operands)?,
+            Query::Not(operand) => {
+                let excluded = operand.matching_documents(index)?;
+                let mut matching: BTreeSet<DocumentId> = all_documents();
+                matching.retain(|document_id| !excluded.contains(document_id));
+                matching
+            }
+            Query::Clauses {
+                required,
+                optional,
+                excluded,
+            } => {
+                let mut matching = if !required.is_empty() {
+                    intersection(index, required)?
+                } else if !optional.is_empty() {
+                    union(index, optional)?
+                } else if !excluded.is_empty() {
+                    all_documents()
+                } else {
+                    BTreeSet::new()
+                };
+                let excluded = union(index, excluded)?;
+                matching.retain(|document_id| !excluded.contains(document_id));
+                matching
+            }
+        })
+    }
+
+    /// Terms with their weights and phrases contributing to the score of matching
+    /// documents, that is those not negated or excluded.
+    pub fn scored_terms(&self) -> (Vec<(&String, f32)>, Vec<&Phrase>) {
+        let mut terms = Vec::new();
+        let mut phrases = Vec::new();
+        self.collect_scored_terms(&mut terms, &mut phrases);
+        (terms, phrases)
+    }
+
+    fn collect_scored_terms<'a>(
+        &'a self,
+        terms: &mut Vec<(&'a String, f32)>,
+        phrases: &mut Vec<&'a Phrase>,
+    ) {
+        match self {
+            Query::Term(term) => terms.push((term, 1.0)),
+            Query::Weighted { term, weight } => terms.push((term, *weight)),
+            Query::Phrase(phrase) => phrases.push(phrase),
+            Query::And(operands) | Query::Or(operands) => {
+                for operand in operands {
+                    operand.collect_scored_terms(terms, phrases);
+                }
+            }
+            Query::Wildcard(_) | Query::Fuzzy { .. } | Query::Not(_) => {}
+            Query::Clauses {
+                required, optional, ..
+            } => {
+                for clause in required.iter().chain(optional) {
+                    clause.collect_scored_terms(terms, phrases);
+                }
+            }
+        }
+    }
+}
+
+/// A query whose wildcards and fuzzy terms were replaced by the matching terms of an index.
+#[derive(Debug)]
+pub struct Expansion {
+    pub query: Query,
+    /// Wildcards and fuzzy terms matching more terms than allowed, of which only the first
+    /// terms in sorted order, or the most similar ones, are searched
+    pub truncated: Vec<String>,
+    /// Terms missing from the index that were replaced by similar terms
+    pub fuzzy_fallbacks: Vec<String>,
+}
+
+/// Scale of the score of a term `edits` edits away from the one searched for.
+fn fuzzy_weight(edits: u32) -> f32 {
+    1.0 / (1 + edits) as f32
+}
+
+/// Edits allowed when looking for terms similar to a missing one, more for longer terms.
+fn fallback_edits(term: &str) -> u32 {
+    if !term.chars().all(char::is_alphabetic) {
+        // numbers and symbols one edit apart are unrelated
+        return 0;
+    }
+    match term.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => MAX_EDITS,
+    }
+}
+
+struct Expander<'a, I: ?Sized> {
+    index: &'a I,
+    max_expansions: usize,
+    fuzzy_fallback: bool,
+    truncated: Vec<String>,
+    fuzzy_fallbacks: Vec<String>,
+}
+
+impl<I> Expander<'_, I>
+where
+    I: IndexReader + ?Sized,
+{
+    fn expand_all(
+        &mut self,
+        queries: Vec<Query>,
+        negated: bool,
+    ) -> Result<Vec<Query>, anyhow::Error> {
+        queries
+            .into_iter()
+            .map(|query| self.expand(query, negated))
+            .collect()
+    }
+
+    /// Missing terms are only replaced where they add to the score, a typo in an excluded
+    /// term should not exclude the documents containing similar terms.
+    fn expand(&mut self, query: Query, negated: bool) -> Result<Query, anyhow::Error> {
+        Ok(match query {
+            Query::Wildcard(pattern) => {
+                let (terms, is_truncated) =
+                    wildcard_terms(self.index, &pattern, self.max_expansions)?;
+                if is_truncated {
+                    self.truncated.push(pattern);
+                }
+                Query::Or(terms.into_iter().map(Query::Term).collect())
+            }
+            Query::Fuzzy { term, max_edits } => {
+                let (terms, is_truncated) =
+                    similar_terms(self.index, &term, max_edits, self.max_expansions)?;
+                if is_truncated {
+                    self.truncated.push(format!("{term}~{max_edits}"));
+                }
+                weighted_alternatives(terms)
+            }
+            Query::Term(term) if self.fuzzy_fallback && !negated => {
+                let max_edits = fallback_edits(&term);
+                if max_edits == 0 || contains_term(self.index, &term)? {
+                    return Ok(Query::Term(term));
+                }
+                let (terms, _) = similar_terms(self.index, &term, max_edits, self.max_expansions)?;
+                self.fuzzy_fallbacks.push(term);
+                weighted_alternatives(terms)
+            }
+            Query::And(operands) => Query::And(self.expand_all(operands, negated)?),
+            Query::Or(operands) => Query::Or(self.expand_all(operands, negated)?),
+            Query::Not(operand) => Query::Not(Box::new(self.expand(*operand, !negated)?)),
+            Query::Clauses {
+                required,
+                optional,
+                excluded,
+            } => Query::Clauses {
+                required: self.expand_all(required, negated)?,
+                optional: self.expand_all(optional, negated)?,
+                excluded: self.expand_all(excluded, !negated)?,
+            },
+            query @ (Query::Term(_) | Query::Phrase(_) | Query::Weighted { .. }) => query,
+        })
+    }
+}
+
+impl Query {
+    /// Replaces every wildcard and fuzzy term by the alternative of the at most
+    /// `max_expansions` terms of the index it matches. With `fuzzy_fallback`, terms missing
+    /// from the index are replaced by the terms similar to them as well. Terms found by
+    /// fuzzy matching score less the more edits away they are.
+    pub fn expand<I>(
+        self,
+        index: &I,
+        max_expansions: usize,
+        fuzzy_fallback: bool,
+    ) -> Result<Expansion, anyhow::Error>
+    where
+        I: IndexReader + ?Sized,
+    {
+        let mut expander = Expander {
+            index,
+            max_expansions,
+            fuzzy_fallback,
+            truncated: Vec::new(),
+            fuzzy_fallbacks: Vec::new(),
+        };
+        let query = expander.expand(self, false)?;
+        Ok(Expansion {
+            query,
+            truncated: expander.truncated,
+            fuzzy_fallbacks: expander.fuzzy_fallbacks,
+        })
+    }
+}
+
+fn weighted_alternatives(terms: Vec<(String, u32)>) -> Query {
+    Query::Or(
+        terms
+            .into_iter()
+            .map(|(term, edits)| Query::Weighted {
+                term,
+                weight: fuzzy_weight(edits),
+            })
+            .collect(),
+    )
+}
+
+fn contains_term<I>(index: &I, term: &str) -> Result<bool, anyhow::Error>
+where
+    I: IndexReader + ?Sized,
+{
+    let mut found = false;
+    index.visit_terms(term, &mut |candidate| {
+        found = candidate == term;
+        false
+    })?;
+    Ok(found)
+}
+
+/// Terms within `max_edits` edits of `term` with their edit distance, the closest ones
+/// first, and whether there were more than `max_expansions`.
+///
+/// Runs a Levenshtein automaton over the sorted dictionary: the rows of the edit distance
+/// matrix of a term are reused for the next term sharing a prefix with it, and terms
+/// starting with a prefix that is already too far away are skipped.
+fn similar_terms<I>(
+    index: &I,
+    term: &str,
+    max_edits: u32,
+    max_expansions: usize,
+) -> Result<(Vec<(String, u32)>, bool), anyhow::Error>
+where
+    I: IndexReader + ?Sized,
+{
+    let target: Vec<char> = term.chars().collect();
+    // rows[i] holds the distances between the first i characters of the candidate and
+    // every prefix of the target
+    let mut rows: Vec<Vec<u32>> = vec![(0..=target.len() as u32).collect()];
+    let mut previous: Vec<char> = Vec::new();
+    // length of the prefix of `previous` no match can start with
+    let mut dead_prefix: Option<usize> = None;
+    let mut similar = Vec::new();
+
+    index.visit_terms("", &mut |candidate| {
+        let candidate: Vec<char> = candidate.chars().collect();
+        let shared = candidate
+            .iter()
+            .zip(&previous)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if dead_prefix.is_some_and(|dead_prefix| dead_prefix <= shared) {
+            previous = candidate;
+            return true;
+        }
+        dead_prefix = None;
+        rows.truncate(shared + 1);
+
+        for (i, c) in candidate.iter().enumerate().skip(shared) {
+            let last = &rows[i];
+            let mut row = Vec::with_capacity(last.len());
+            row.push(last[0] + 1);
+            for (j, target_char) in target.iter().enumerate() {
+                let substitution = last[j] + u32::from(c != target_char);
+                row.push(substitution.min(last[j + 1] + 1).min(row[j] + 1));
+            }
+            let is_dead = row.iter().all(|distance| *distance > max_edits);
+            rows.push(row);
+            if is_dead {
+                dead_prefix = Some(i + 1);
+                break;
+            }
+        }
+        if dead_prefix.is_none() {
+            let distance = rows[candidate.len()][target.len()];
+            if distance <= max_edits {
+                similar.push((candidate.iter().collect::<String>(), distance));
+            }
+        }
+        previous = candidate;
+        true
+    })?;
+
+    similar.sort_by(|(a, a_distance), (b, b_distance)| a_distance.cmp(b_distance).then(a.cmp(b)));
+    let is_truncated = similar.len() > max_expansions;
+    similar.truncate(max_expansions);
+    Ok((similar, is_truncated))
+}
+
+/// Terms matching a wildcard pattern, and whether there were more than `max_expansions`.
+/// Only the terms starting with the literal part before the first wildcard are visited.
+fn wildcard_terms<I>(
+    index: &I,
+    pattern: &str,
+    max_expansions: usize,
+) -> Result<(Vec<String>, bool), anyhow::Error>
+where
+    I: IndexReader + ?Sized,
+{
+    let pattern_chars: Vec<char> = pattern.chars().collect();
+    let prefix: String = pattern
+        .chars()
+        .take_while(|c| !matches!(c, '*' | '?'))
+        .collect();
+    let mut terms = Vec::new();
+    let mut is_truncated = false;
+    index.visit_terms(&prefix, &mut |term| {
+        if !wildcard_matches(&pattern_chars, term) {
+            return true;
+        }
+        if terms.len() == max_expansions {
+            is_truncated = true;
+            return false;
+        }
+        terms.push(term.to_string());
+        true
+    })?;
+    Ok((terms, is_truncated))
+}
+
+fn wildcard_matches(pattern: &[char], term: &str) -> bool {
+    let term: Vec<char> = term.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // last star seen and the position in the term it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while t < term.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == term[t] => {
+                p += 1;
+                t += 1;
+            }
+            // let the last star absorb one more character
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn intersection<I>(index: &I, operands: &[Query]) -> Result<BTreeSet<DocumentId>, anyhow::Error>
+where
+    I: IndexReader + ?Sized,
+{
+    let Some((first, rest)) = operands.split_first() else {
+        return Ok(BTreeSet::new());
+    };
+    let mut matching = first.matching_documents(index)?;
+    for operand in rest {
+        if matching.is_empty() {
+            break;
+        }
+        let other = operand.matching_documents(index)?;
+        matching.retain(|document_id| other.contains(document_id));
+    }
+    Ok(matching)
+}
+
+fn union<I>(index: &I, operands: &[Query]) -> Result<BTreeSet<DocumentId>, anyhow::Error>
+where
+    I: IndexReader + ?Sized,
+{
+    let mut matching = BTreeSet::new();
+    for operand in operands {
+        matching.extend(operand.matching_documents(index)?);
+    }
+    Ok(matching)
+}
+
+/// Documents containing the phrase, with the number of times it occurs in each of them.
+pub fn phrase_matches<I>(
+    index: &I,
+    phrase: &Phrase,
+) -> Result<Vec<(DocumentId, u32)>, anyhow::Error>
+where
+    I: IndexReader + ?Sized,
+{
+    let term_postings = phrase
+        .terms
+        .iter()
+        .map(|term| index.postings(term))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some((first, rest)) = term_postings.split_first() else {
+        return Ok(Vec::new());
+    };
+
+    let mut matches = Vec::new();
+    'documents: for posting in first.iter() {
+        let mut term_positions = vec![posting.positions.as_slice()];
+        for postings in rest {
+            // postings are sorted by document id
+            match postings.binary_search_by_key(&posting.document_id, |other| other.document_id) {
+                Ok(found) => term_positions.push(postings[found].positions.as_slice()),
+                Err(_) => continue 'documents,
+            }
+        }
+        let frequency = phrase_frequency(&term_positions, phrase.slop);
+        if frequency > 0 {
+            matches.push((posting.document_id, frequency));
+        }
+    }
+    Ok(matches)
+}
+
+/// Number of times a phrase occurs, given the sorted positions of each of its terms in a
+/// document. Every occurrence of the first term starts at most one match, completed by the
+/// nearest following occurrence of each next term.
+pub fn phrase_frequency(term_positions: &[&[u32]], slop: u32) -> u32 {
+    let Some((first, rest)) = term_positions.split_first() else {
+        return 0;
+    };
+
+    let mut frequency = 0;
+    'starts: for &start in first.iter() {
+        let mut previous = start;
+        for positions in rest {
+            let next = positions.partition_point(|position| *position <= previous);
+            match positions.get(next) {
+                Some(position) => previous = *position,
+                // later starts cannot be completed either
+                None => break 'starts,
+            }
+        }
+        let gaps = previous - start - rest.len() as u32;
+        if gaps <= slop {
+            frequency += 1;
+        }
+    }
+    frequency
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    use crate::query::{
+        Phrase, Query, QueryParseError, phrase_frequency, similar_terms, wildcard_matches,
+    };
+    use crate::term_frequency::TermFrequency;
+    use crate::utils::Index;
+
+    fn term(term: &str) -> Query {
+        Query::Term(term.to_string())
+    }
+
+    fn phrase(terms: &[&str], slop: u32) -> Query {
+        Query::Phrase(Phrase {
+            terms: terms.iter().map(|term| term.to_string()).collect(),
+            slop,
+        })
+    }
+
+    #[test]
+    fn quoted_phrases_are_parsed() {
+        let query = Query::parse(r#"Deep "machine learning"~3 "neural NETWORKS" e-mail"#).unwrap();
+
+        assert_eq!(
+            query,
+            Query::Clauses {
+                required: vec![],
+                optional: vec![
+                    term("deep"),
+                    phrase(&["machine", "learning"], 3),
+                    phrase(&["neural", "networks"], 0),
+                    phrase(&["e", "-", "mail"], 0)
+                ],
+                excluded: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn boolean_operators_bind_tighter_than_spaces() {
+        let query = Query::parse("rome AND (empire OR republic) -italy +NOT gaul").unwrap();
+
+        assert_eq!(
+            query,
+            Query::Clauses {
+                required: vec![Query::Not(Box::new(term("gaul")))],
+                optional: vec![Query::And(vec![
+                    term("rome"),
+                    Query::Or(vec![term("empire"), term("republic")])
+                ])],
+                excluded: vec![term("italy")]
+            }
+        );
+        assert_eq!(
+            Query::parse("a OR b AND c").unwrap(),
+            Query::Or(vec![term("a"), Query::And(vec![term("b"), term("c")])])
+        );
+        assert_eq!(
+            Query::parse("and or - +").unwrap().scored_terms().0.len(),
+            4
+        );
+    }
+
+    #[test]
+    fn malformed_queries_are_rejected() {
+        assert_eq!(
+            Query::parse(r#"rome "ancient empire"#),
+            Err(QueryParseError::UnterminatedPhrase(5))
+        );
+        assert_eq!(
+            Query::parse(r#""ancient empire"~ rome"#),
+            Err(QueryParseError::InvalidSlop(16))
+        );
+        assert_eq!(
+            Query::parse("rome AND"),
+            Err(QueryParseError::ExpectedTerm(8))
+        );
+        assert_eq!(
+            Query::parse("(rome OR gaul"),
+            Err(QueryParseError::UnclosedGroup(0))
+        );
+        assert_eq!(
+            Query::parse("rome) gaul"),
+            Err(QueryParseError::UnmatchedClose(4))
+        );
+    }
+
+    #[test]
+    fn queries_are_evaluated_over_the_index() {
+        let mut docs = Vec::new();
+        for (path, text) in [
+            ("empire.txt", "rome empire"),
+            ("republic.txt", "rome republic italy"),
+            ("gaul.txt", "gaul empire"),
+        ] {
+            let mut tf = TermFrequency::new(PathBuf::from(path));
+            for token in text.split(' ') {
+                tf.update(token);
+            }
+            docs.push(tf);
+        }
+        let index = Index::from_term_frequencies(docs);
+        let matching = |query: &str| -> BTreeSet<u32> {
+            Query::parse(query)
+                .unwrap()
+                .matching_documents(&index)
+                .unwrap()
+        };
+
+        assert_eq!(
+            matching("rome AND (empire OR republic) -italy"),
+            BTreeSet::from([0])
+        );
+        assert_eq!(matching("+empire rome"), BTreeSet::from([0, 2]));
+        assert_eq!(matching("NOT rome"), BTreeSet::from([2]));
+        assert_eq!(matching("-empire"), BTreeSet::from([1]));
+        assert_eq!(matching(r#""rome empire" OR gaul"#), BTreeSet::from([0, 2]));
+        assert_eq!(matching(""), BTreeSet::new());
+        assert_eq!(matching("empire -(rome OR italy)"), BTreeSet::from([2]));
+        assert_eq!(matching("+(rome empire) gaul"), BTreeSet::from([0, 1, 2]));
+        assert_eq!(matching(r#"empire -"rome empire""#), BTreeSet::from([2]));
+    }
+
+    #[test]
+    fn signs_apply_to_groups_and_phrases() {
+        assert_eq!(
+            Query::parse("rome -(italy OR gaul)").unwrap(),
+            Query::Clauses {
+                required: vec![],
+                optional: vec![term("rome")],
+                excluded: vec![Query::Or(vec![term("italy"), term("gaul")])]
+            }
+        );
+        assert_eq!(
+            Query::parse("+(rome empire)").unwrap(),
+            Query::Clauses {
+                required: vec![Query::Clauses {
+                    required: vec![],
+                    optional: vec![term("rome"), term("empire")],
+                    excluded: vec![]
+                }],
+                optional: vec![],
+                excluded: vec![]
+            }
+        );
+        assert_eq!(
+            Query::parse(r#"rome -"roman empire""#).unwrap(),
+            Query::Clauses {
+                required: vec![],
+                optional: vec![term("rome")],
+                excluded: vec![phrase(&["roman", "empire"], 0)]
+            }
+        );
+    }
+
+    #[test]
+    fn wildcards_expand_to_matching_terms() {
+        let mut tf = TermFrequency::new(PathBuf::from("words.txt"));
+        for token in [
+            "commuter",
+            "compute",
+            "computer",
+            "computing",
+            "tent",
+            "test",
+            "text",
+            "toast",
+        ] {
+            tf.update(token);
+        }
+        let index = Index::from_term_frequencies(vec![tf]);
+        let expand = |query: &str, max_expansions: usize| {
+            Query::parse(query)
+                .unwrap()
+                .expand(&index, max_expansions, false)
+                .unwrap()
+        };
+
+        let expansion = expand("Comput* te?t", 10);
+        assert_eq!(
+            expansion.query,
+            Query::Clauses {
+                required: vec![],
+                optional: vec![
+                    Query::Or(vec![term("compute"), term("computer"), term("computing")]),
+                    Query::Or(vec![term("tent"), term("test"), term("text")])
+                ],
+                excluded: vec![]
+            }
+        );
+        assert!(expansion.truncated.is_empty());
+
+        let expansion = expand("comput* -t*t", 2);
+        assert_eq!(expansion.truncated, ["comput*", "t*t"]);
+        assert_eq!(
+            expansion.query.matching_documents(&index).unwrap(),
+            BTreeSet::new()
+        );
+
+        assert!(
+            Query::parse("comput*")
+                .unwrap()
+                .matching_documents(&index)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn wildcards_only_extend_tokens() {
+        let term = |term: &str| Query::Term(term.to_string());
+        let wildcard = |pattern: &str| Query::Wildcard(pattern.to_string());
+
+        assert_eq!(Query::parse("Comput*").unwrap(), wildcard("comput*"));
+        assert_eq!(Query::parse("Te?t").unwrap(), wildcard("te?t"));
+        assert_eq!(Query::parse("c*t*r").unwrap(), wildcard("c*t*r"));
+        // a trailing question mark or a leading wildcard is punctuation
+        assert_eq!(
+            Query::parse("is rome?").unwrap(),
+            Query::parse("is rome").unwrap()
+        );
+        assert_eq!(Query::parse("*ing").unwrap(), term("ing"));
+        assert_eq!(Query::parse("?"), Err(QueryParseError::ExpectedTerm(0)));
+        // literal parts are tokenized like documents
+        assert_eq!(
+            Query::parse("Foo-Bar*").unwrap(),
+            Query::And(vec![term("foo"), term("-"), wildcard("bar*")])
+        );
+    }
+
+    #[test]
+    fn wildcard_patterns_match_whole_terms() {
+        let matches = |pattern: &str, term: &str| {
+            wildcard_matches(&pattern.chars().collect::<Vec<char>>(), term)
+        };
+
+        assert!(matches("comput*", "comput"));
+        assert!(matches("*ing", "computing"));
+        assert!(matches("c*t*r", "computer"));
+        assert!(matches("te?t", "text"));
+        assert!(!matches("te?t", "tet"));
+        assert!(!matches("*ing", "ingot"));
+        assert!(matches("*", ""));
+    }
+
+    fn create_vocabulary_index(terms: &[&str]) -> Index {
+        let mut tf = TermFrequency::new(PathBuf::from("words.txt"));
+        for term in terms {
+            tf.update(term);
+        }
+        Index::from_term_frequencies(vec![tf])
+    }
+
+    #[test]
+    fn fuzzy_terms_are_parsed() {
+        assert_eq!(
+            Query::parse("Algoritm~1").unwrap(),
+            Query::Fuzzy {
+                term: String::from("algoritm"),
+                max_edits: 1
+            }
+        );
+        // without a number of edits the word is tokenized like any other
+        assert_eq!(
+            Query::parse("foo~bar").unwrap(),
+            Query::Phrase(Phrase {
+                terms: vec![String::from("foo"), String::from("~"), String::from("bar")],
+                slop: 0
+            })
+        );
+        for query in ["foo~", "~1", "foo~1x"] {
+            assert!(!matches!(
+                Query::parse(query),
+                Ok(Query::Fuzzy { .. }) | Err(_)
+            ));
+        }
+        assert_eq!(
+            Query::parse("rome algoritm~3"),
+            Err(QueryParseError::TooManyEdits(13))
+        );
+    }
+
+    #[test]
+    fn similar_terms_are_found_in_the_dictionary() {
+        let index =
+            create_vocabulary_index(&["algebra", "algorithm", "algorithms", "logarithm", "rhythm"]);
+        let similar = |term: &str, max_edits: u32, max_expansions: usize| {
+            similar_terms(&index, term, max_edits, max_expansions).unwrap()
+        };
+
+        assert_eq!(
+            similar("algoritm", 1, 10),
+            (vec![(String::from("algorithm"), 1)], false)
+        );
+        assert_eq!(
+            similar("algoritms", 2, 10),
+            (
+                vec![
+                    (String::from("algorithms"), 1),
+                    (String::from("algorithm"), 2)
+                ],
+                false
+            )
+        );
+        assert_eq!(
+            similar("algoritms", 2, 1),
+            (vec![(String::from("algorithms"), 1)], true)
+        );
+        assert_eq!(similar("rhythm", 0, 10).0, [(String::from("rhythm"), 0)]);
+    }
+
+    #[test]
+    fn missing_terms_fall_back_to_similar_terms() {
+        let index = create_vocabulary_index(&["algorithm", "rome"]);
+        let expand = |query: &str, fuzzy_fallback: bool| {
+            Query::parse(query)
+                .unwrap()
+                .expand(&index, 10, fuzzy_fallback)
+                .unwrap()
+        };
+
+        let expansion = expand("algoritm rome -romee", true);
+        assert_eq!(
+            expansion.query,
+            Query::Clauses {
+                required: vec![],
+                optional: vec![
+                    Query::Or(vec![Query::Weighted {
+                        term: String::from("algorithm"),
+                        weight: 0.5
+                    }]),
+                    term("rome")
+                ],
+                excluded: vec![term("romee")]
+            }
+        );
+        assert_eq!(expansion.fuzzy_fallbacks, ["algoritm"]);
+
+        let expansion = expand("algoritm", false);
+        assert_eq!(expansion.query, term("algoritm"));
+        assert!(expansion.fuzzy_fallbacks.is_empty());
+    }
+
+    #[test]
+    fn phrases_match_terms_in_order() {
+        let machine: &[u32] = &[0, 10, 20];
+        let learning: &[u32] = &[1, 9, 23];
+
+        assert_eq!(phrase_frequency(&[machine, learning], 0), 1);
+        assert_eq!(phrase_frequency(&[machine, learning], 2), 2);
+        assert_eq!(phrase_frequency(&[learning, machine], 0), 1);
+        assert_eq!(phrase_frequency(&[machine, &[]], 5), 0);
+    }
+}
@@ -0,0 +1,314 @@
+use anyhow;
+use std::collections::HashMap;
+
+use crate::inverted_index::DocumentId;
+use crate::query::{self, Query};
+use crate::utils::{IndexReader, SearchResult};
+
+/// Default term frequency saturation for BM25.
+pub const DEFAULT_K1: f32 = 1.2;
+/// Default document length normalization for BM25.
+pub const DEFAULT_B: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ranking {
+    TfIdf,
+    Bm25 { k1: f32, b: f32 },
+}
+
+impl Ranking {
+    pub fn bm25() -> Self {
+        Self::Bm25 {
+            k1: DEFAULT_K1,
+            b: DEFAULT_B,
+        }
+    }
+
+    /// Contribution of a single query term to the score of a document.
+    pub fn term_score(
+        &self,
+        term_freq: u32,
+        inverse_doc_freq: f32,
+        document_length: u32,
+        average_document_length: f32,
+    ) -> f32 {
+        let term_freq = term_freq as f32;
+        match *self {
+            Self::TfIdf => term_freq * inverse_doc_freq,
+            Self::Bm25 { k1, b } => {
+                // empty corpora have no meaningful average, skip the normalization
+                let length_ratio = if average_document_length > 0.0 {
+                    document_length as f32 / average_document_length
+                } else {
+                    1.0
+                };
+                let normalization = k1 * (1.0 - b + b * length_ratio);
+                inverse_doc_freq * (term_freq * (k1 + 1.0)) / (term_freq + normalization)
+            }
+        }
+    }
+
+    /// Scores every document matching the query as the sum of the contributions of the
+    /// terms and phrases it contains, visiting only the postings of the query terms. A
+    /// phrase counts as a single term weighted by the sum of the IDF of its terms, and
+    /// negated or excluded terms do not contribute.
+    pub fn search<I>(&self, index: &I, query: &Query) -> Result<Vec<SearchResult>, anyhow::Error>
+    where
+        I: IndexReader + ?Sized,
+    {
+        let matching = query.matching_documents(index)?;
+        let mut scorer = Scorer {
+            ranking: *self,
+            index,
+            average_document_length: index.average_document_length(),
+            scores: HashMap::new(),
+            lengths: HashMap::new(),
+        };
+
+        let (terms, phrases) = query.scored_terms();
+        for (term, weight) in terms {
+            let inverse_doc_freq = index.inverse_document_frequency(term)?;
+            for posting in index.postings(term)?.iter() {
+                if matching.contains(&posting.document_id) {
+                    scorer.add(
+                        posting.document_id,
+                        posting.term_freq,
+                        inverse_doc_freq,
+                        weight,
+                    )?;
+                }
+            }
+        }
+        for phrase in phrases {
+            let inverse_doc_freq = phrase
+                .terms
+                .iter()
+                .map(|term| index.inverse_document_frequency(term))
+                .sum::<Result<f32, _>>()?;
+            for (document_id, phrase_freq) in query::phrase_matches(index, phrase)? {
+                if matching.contains(&document_id) {
+                    scorer.add(document_id, phrase_freq, inverse_doc_freq, 1.0)?;
+                }
+            }
+        }
+        let scores = scorer.scores;
+
+        matching
+            .into_iter()
+            .map(|document_id| {
+                let document = index.document(document_id)?;
+                // documents only matched through negations have no score
+                let score = scores.get(&document_id).copied().unwrap_or_default();
+                Ok(SearchResult {
+                    document_path: document.path.clone(),
+                    title: document.title.clone(),
+                    score,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Accumulates the scores of the documents matching a query.
+struct Scorer<'a, I: ?Sized> {
+    ranking: Ranking,
+    index: &'a I,
+    average_document_length: f32,
+    scores: HashMap<DocumentId, f32>,
+    // documents are decoded once even when they match several terms
+    lengths: HashMap<DocumentId, u32>,
+}
+
+impl<I> Scorer<'_, I>
+where
+    I: IndexReader + ?Sized,
+{
+    fn add(
+        &mut self,
+        document_id: DocumentId,
+        term_freq: u32,
+        inverse_doc_freq: f32,
+        weight: f32,
+    ) -> Result<(), anyhow::Error> {
+        let document_length = match self.lengths.get(&document_id) {
+            Some(length) => *length,
+            None => {
+                let length = self.index.document(document_id)?.length;
+                self.lengths.insert(document_id, length);
+                length
+            }
+        };
+        *self.scores.entry(document_id).or_default() += weight
+            * self.ranking.term_score(
+                term_freq,
+                inverse_doc_freq,
+                document_length,
+                self.average_document_length,
+            );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::query::Query;
+    use crate::ranking::Ranking;
+    use crate::term_frequency::TermFrequency;
+    use crate::utils::Index;
+
+    fn create_index() -> Index {
+        let mut short = TermFrequency::new(PathBuf::from("short.xml"));
+        for token in ["roman", "empire", "italy"] {
+            short.update(token);
+        }
+        let mut long = TermFrequency::new(PathBuf::from("long.xml"));
+        long.update("roman");
+        long.update("roman");
+        for _ in 0..38 {
+            long.update("filler");
+        }
+        let mut docs = vec![short, long];
+        for i in 0..6 {
+            docs.push(TermFrequency::new(PathBuf::from(format!("empty_{i}.xml"))));
+        }
+        Index::from_term_frequencies(docs)
+    }
+
+    fn score_of(ranking: Ranking, index: &Index, query: &str, document: &str) -> f32 {
+        ranking
+            .search(index, &Query::parse(query).unwrap())
+            .unwrap()
+            .into_iter()
+            .find(|result| result.document_path == Path::new(document))
+            .map(|result| result.score)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn multi_term_score_sums_every_term() {
+        let index = create_index();
+
+        let single = score_of(Ranking::TfIdf, &index, "roman", "short.xml");
+        let multi = score_of(Ranking::TfIdf, &index, "roman empire", "short.xml");
+
+        assert!(multi > single);
+    }
+
+    #[test]
+    fn only_matching_documents_are_returned() {
+        let index = create_index();
+
+        let results = Ranking::TfIdf
+            .search(&index, &Query::parse("empire").unwrap())
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_path, Path::new("short.xml"));
+    }
+
+    #[test]
+    fn boolean_queries_filter_before_ranking() {
+        let index = create_index();
+
+        let results = Ranking::bm25()
+            .search(&index, &Query::parse("roman -italy").unwrap())
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_path, Path::new("long.xml"));
+
+        // the negated term does not add to the score
+        let with_not = score_of(Ranking::TfIdf, &index, "roman AND NOT filler", "short.xml");
+        let without = score_of(Ranking::TfIdf, &index, "roman", "short.xml");
+        assert_eq!(with_not, without);
+    }
+
+    #[test]
+    fn phrases_only_match_terms_in_order() {
+        let mut docs = Vec::new();
+        for (path, text) in [
+            ("phrase.txt", "machine learning models"),
+            ("apart.txt", "learning about the machine"),
+            ("gap.txt", "machine based deep learning"),
+        ] {
+            let mut tf = TermFrequency::new(PathBuf::from(path));
+            for token in text.split(' ') {
+                tf.update(token);
+            }
+            docs.push(tf);
+        }
+        let index = Index::from_term_frequencies(docs);
+        let matches = |query: &str| -> Vec<PathBuf> {
+            let mut paths: Vec<PathBuf> = Ranking::bm25()
+                .search(&index, &Query::parse(query).unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|result| result.document_path)
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(matches(r#""machine learning""#), [Path::new("phrase.txt")]);
+        assert_eq!(
+            matches(r#""machine learning"~2"#),
+            [Path::new("gap.txt"), Path::new("phrase.txt")]
+        );
+        assert_eq!(
+            matches(r#""machine learning" about"#),
+            [Path::new("apart.txt"), Path::new("phrase.txt")]
+        );
+    }
+
+    #[test]
+    fn fuzzy_matches_score_lower_than_exact_ones() {
+        let mut docs = Vec::new();
+        for (path, token) in [("exact.txt", "algorithm"), ("typo.txt", "algoritm")] {
+            let mut tf = TermFrequency::new(PathBuf::from(path));
+            tf.update(token);
+            docs.push(tf);
+        }
+        let index = Index::from_term_frequencies(docs);
+        let query = Query::parse("algorithm~1")
+            .unwrap()
+            .expand(&index, 10, true)
+            .unwrap()
+            .query;
+
+        let results = Ranking::bm25().search(&index, &query).unwrap();
+        let score = |path: &str| {
+            results
+                .iter()
+                .find(|result| result.document_path == Path::new(path))
+                .unwrap()
+                .score
+        };
+
+        assert!(score("exact.txt") > score("typo.txt"));
+        assert!(score("typo.txt") > 0.0);
+    }
+
+    #[test]
+    fn bm25_normalizes_document_length() {
+        let index = create_index();
+
+        let tfidf_short = score_of(Ranking::TfIdf, &index, "roman", "short.xml");
+        let tfidf_long = score_of(Ranking::TfIdf, &index, "roman", "long.xml");
+        let bm25_short = score_of(Ranking::bm25(), &index, "roman", "short.xml");
+        let bm25_long = score_of(Ranking::bm25(), &index, "roman", "long.xml");
+
+        assert!(tfidf_long > tfidf_short);
+        assert!(bm25_short > bm25_long);
+    }
+
+    #[test]
+    fn bm25_without_length_normalization_ignores_length() {
+        let ranking = Ranking::Bm25 { k1: 1.2, b: 0.0 };
+
+        let short = ranking.term_score(2, 1.0, 10, 100.0);
+        let long = ranking.term_score(2, 1.0, 1000, 100.0);
+
+        assert_eq!(short, long);
+    }
+}
@@ -0,0 +1,272 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::inverse_doc_frequency::smoothed_idf;
+use crate::os_interaction::RegistryEntry;
+use crate::parser_registry::ParserRegistry;
+use crate::utils::Index;
+
+/// Format name used for documents no registered parser recognizes.
+const UNKNOWN_FORMAT: &str = "unknown";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TermStatistics {
+    pub term: String,
+    /// Occurrences across all documents
+    pub frequency: u64,
+    pub document_frequency: usize,
+    pub inverse_document_frequency: f32,
+}
+
+/// Summary of an index and of the way it was built.
+#[derive(Serialize, Debug)]
+pub struct IndexStatistics {
+    pub index_name: String,
+    pub source_directory: Option<PathBuf>,
+    pub document_count: usize,
+    pub vocabulary_size: usize,
+    pub total_tokens: u64,
+    pub average_document_length: f32,
+    pub size_on_disk: u64,
+    pub documents_per_format: BTreeMap<String, usize>,
+    /// Seconds since the unix epoch
+    pub created_at: Option<u64>,
+    /// Seconds since the unix epoch
+    pub last_refreshed_at: Option<u64>,
+    pub build_duration_ms: Option<u64>,
+    pub most_frequent_terms: Vec<TermStatistics>,
+    pub highest_idf_terms: Vec<TermStatistics>,
+}
+
+impl IndexStatistics {
+    /// Computes the statistics of `index`, listing `top_terms` terms per ranking. The
+    /// registry entry is missing for indexes that were never registered.
+    pub fn new(
+        index_name: &str,
+        index: &Index,
+        registry_entry: Option<&RegistryEntry>,
+        size_on_disk: u64,
+        parser_registry: &ParserRegistry,
+        top_terms: usize,
+    ) -> Self {
+        let document_count = index.document_count();
+        let mut terms: Vec<TermStatistics> = index
+            .inverted_index
+            .get_inner_map()
+            .iter()
+            .map(|(term, postings)| TermStatistics {
+                term: term.clone(),
+                frequency: postings
+                    .iter()
+                    .map(|posting| posting.term_freq as u64)
+                    .sum(),
+                document_frequency: postings.len(),
+                inverse_document_frequency: smoothed_idf(document_count, postings.len()),
+            })
+            .collect();
+
+        // ties are broken alphabetically so the output is stable
+        terms.sort_by(|a, b| b.frequency.cmp(&a.frequency).then(a.term.cmp(&b.term)));
+        let most_frequent_terms = terms.iter().take(top_terms).cloned().collect();
+        terms.sort_by(|a, b| {
+            b.inverse_document_frequency
+                .total_cmp(&a.inverse_document_frequency)
+                .then(a.term.cmp(&b.term))
+        });
+        let highest_idf_terms = terms.into_iter().take(top_terms).collect();
+
+        let mut documents_per_format = BTreeMap::new();
+        for document in &index.documents {
+            // indexes built before formats were recorded guess them from the files
+            let format = match &document.format {
+                Some(format) => format.as_str(),
+                None => parser_registry
+                    .parser_for_path(&document.path)
+                    .map_or(UNKNOWN_FORMAT, |parser| parser.name()),
+            };
+            *documents_per_format.entry(format.to_string()).or_default() += 1;
+        }
+
+        Self {
+            index_name: index_name.to_string(),
+            source_directory: registry_entry.map(|entry| entry.source_directory.clone()),
+            document_count,
+            vocabulary_size: index.term_count(),
+            total_tokens: index
+                .documents
+                .iter()
+                .map(|document| document.length as u64)
+                .sum(),
+            average_document_length: index.average_document_length,
+            size_on_disk,
+            documents_per_format,
+            created_at: registry_entry.map(|entry| entry.created_at),
+            last_refreshed_at: registry_entry.map(|entry| entry.last_refreshed_at),
+            build_duration_ms: registry_entry.and_then(|entry| entry.build_duration_ms),
+            most_frequent_terms,
+            highest_idf_terms,
+        }
+    }
+}
+
+/// Formats seconds since the unix epoch as a UTC date and time.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+
+    // civil from days, after Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn write_optional<T>(
+    f: &mut std::fmt::Formatter<'_>,
+    label: &str,
+    value: Option<T>,
+) -> std::fmt::Result
+where
+    T: std::fmt::Display,
+{
+    match value {
+        Some(value) => writeln!(f, "{label}: {value}"),
+        None => writeln!(f, "{label}: unknown"),
+    }
+}
+
+fn display_path(path: &Path) -> String {
+    path.display().to_string()
+}
+
+impl std::fmt::Display for IndexStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Index: {}", self.index_name)?;
+        write_optional(
+            f,
+            "Source directory",
+            self.source_directory.as_deref().map(display_path),
+        )?;
+        writeln!(f, "Documents: {}", self.document_count)?;
+        writeln!(f, "Vocabulary size: {}", self.vocabulary_size)?;
+        writeln!(f, "Total tokens: {}", self.total_tokens)?;
+        writeln!(
+            f,
+            "Average document length: {:.2}",
+            self.average_document_length
+        )?;
+        writeln!(f, "Size on disk: {} bytes", self.size_on_disk)?;
+        write_optional(f, "Created", self.created_at.map(format_timestamp))?;
+        write_optional(
+            f,
+            "Last refreshed",
+            self.last_refreshed_at.map(format_timestamp),
+        )?;
+        write_optional(
+            f,
+            "Build time",
+            self.build_duration_ms
+                .map(|duration| format!("{:.2}s", duration as f64 / 1000.0)),
+        )?;
+
+        writeln!(f, "Documents per format:")?;
+        for (format, count) in &self.documents_per_format {
+            writeln!(f, "\t{format}: {count}")?;
+        }
+        writeln!(f, "Most frequent terms:")?;
+        for term in &self.most_frequent_terms {
+            writeln!(
+                f,
+                "\t{}: {} occurrences in {} documents",
+                term.term, term.frequency, term.document_frequency
+            )?;
+        }
+        writeln!(f, "Highest IDF terms:")?;
+        for term in &self.highest_idf_terms {
+            writeln!(f, "\t{}: {:.2}", term.term, term.inverse_document_frequency)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::parser_registry::ParserRegistry;
+    use crate::statistics::{IndexStatistics, format_timestamp};
+    use crate::term_frequency::TermFrequency;
+    use crate::utils::Index;
+
+    fn create_doc(path: &str, format: Option<&str>, tokens: &[&str]) -> TermFrequency {
+        let mut tf = TermFrequency::new(PathBuf::from(path));
+        for token in tokens {
+            tf.update(token);
+        }
+        tf.format = format.map(str::to_string);
+        tf
+    }
+
+    #[test]
+    fn statistics_are_computed_from_the_index() {
+        let index = Index::from_term_frequencies(vec![
+            create_doc("rome.md", Some("markdown"), &["rome", "rome", "empire"]),
+            // recorded formats win over extensions, as extensionless files are sniffed
+            create_doc("empire", Some("markdown"), &["empire", "rome", "rome"]),
+            create_doc("paris.xml", None, &["paris"]),
+            create_doc("notes", None, &[]),
+        ]);
+
+        let statistics =
+            IndexStatistics::new("docs", &index, None, 123, &ParserRegistry::default(), 2);
+
+        assert_eq!(statistics.document_count, 4);
+        assert_eq!(statistics.vocabulary_size, 3);
+        assert_eq!(statistics.total_tokens, 7);
+        assert_eq!(statistics.average_document_length, 1.75);
+        assert_eq!(statistics.size_on_disk, 123);
+        assert_eq!(statistics.documents_per_format["markdown"], 2);
+        assert_eq!(statistics.documents_per_format["xml"], 1);
+        assert_eq!(statistics.documents_per_format["unknown"], 1);
+        let most_frequent: Vec<&str> = statistics
+            .most_frequent_terms
+            .iter()
+            .map(|term| term.term.as_str())
+            .collect();
+        assert_eq!(most_frequent, ["rome", "empire"]);
+        assert_eq!(statistics.most_frequent_terms[0].frequency, 4);
+        assert_eq!(statistics.highest_idf_terms[0].term, "paris");
+        assert_eq!(statistics.source_directory, None);
+    }
+
+    #[test]
+    fn timestamps_are_formatted_as_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(
+            format_timestamp(1_760_745_600 + 3_723),
+            "2025-10-18 01:02:03 UTC"
+        );
+    }
+}
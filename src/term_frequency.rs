@@ -1,25 +1,50 @@
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::PathBuf};
 
+use crate::fingerprint::FileFingerprint;
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct TermFrequency {
     pub document_path: PathBuf,
+    pub title: Option<String>,
     pub term_freq: HashMap<String, u32>,
+    /// Token positions of every term, in increasing order
+    #[serde(default)]
+    pub positions: HashMap<String, Vec<u32>>,
+    /// Number of tokens in the document
+    pub length: u32,
+    pub fingerprint: FileFingerprint,
+    /// Name of the parser that read the document
+    #[serde(default)]
+    pub format: Option<String>,
 }
 
 impl TermFrequency {
     pub fn new(document_path: PathBuf) -> Self {
         Self {
             document_path,
+            title: None,
             term_freq: HashMap::new(),
+            positions: HashMap::new(),
+            length: 0,
+            fingerprint: FileFingerprint::default(),
+            format: None,
         }
     }
 
+    /// Counts the next token of the document.
     pub fn update(&mut self, term: &str) {
+        let position = self.length;
+        self.length += 1;
         if let Some(count) = self.term_freq.get_mut(term) {
             *count += 1;
+            self.positions
+                .entry(term.to_string())
+                .or_default()
+                .push(position);
         } else {
             self.term_freq.insert(term.to_string(), 1);
+            self.positions.insert(term.to_string(), vec![position]);
         }
     }
 }
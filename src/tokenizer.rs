@@ -42,7 +42,8 @@ impl<'a> Iterator for Tokenizer<'a> {
         }
 
         if self.input[0].is_alphabetic() {
-            return Some(self.chop_while(|x| x.is_alphabetic()).iter().collect());
+            let token: String = self.chop_while(|x| x.is_alphabetic()).iter().collect();
+            return Some(token.to_lowercase());
         }
 
         let token_chars = &self.input[0..1];
@@ -69,6 +70,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tokens_are_lowercased() {
+        let input: Vec<char> = "Ancient ROME".chars().collect();
+
+        let tokens: Vec<String> = Tokenizer::from_chars(&input).collect();
+
+        assert_eq!(tokens, ["ancient", "rome"]);
+    }
+
     #[test]
     fn trim_leading() {
         let input = [' ', ' ', 'b', 'c'];
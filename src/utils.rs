@@ -1,115 +1,405 @@
+use anyhow::{self, Context};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::env::current_dir;
 use std::ffi::OsString;
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::fingerprint::{self, FileFingerprint};
 use crate::inverse_doc_frequency::InverseDocumentFrequency;
-use crate::parsers;
+use crate::inverted_index::{Document, DocumentId, InvertedIndex, Posting};
+use crate::parser_registry::ParserRegistry;
 use crate::term_frequency::TermFrequency;
 use crate::tokenizer::Tokenizer;
 
 #[derive(Debug)]
-pub struct TfIdf {
+pub struct SearchResult {
     pub document_path: PathBuf,
+    pub title: Option<String>,
     pub score: f32,
 }
 
-impl TfIdf {
-    pub fn new(
-        term: &str,
-        doc: &TermFrequency,
-        idf: &InverseDocumentFrequency,
-        docs_count: usize,
-    ) -> Self {
-        let lowercase_term = term.to_lowercase();
-        let term_freq = doc.term_freq.get(&lowercase_term).copied().unwrap_or(0);
+impl std::fmt::Display for SearchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.document_path.display())?;
+        if let Some(title) = &self.title {
+            write!(f, "\n\tTitle: {}", title)?;
+        }
+        write!(f, "\n\tScore: {:.2}", self.score)
+    }
+}
 
-        //let inverse_doc_freq = idf.0.get(&lowercase_term).ok_or("Term is not in IDF index.")?;
+/// Splits a query string into terms using the same tokenizer applied at index time.
+pub fn tokenize_query(query_string: &str) -> Vec<String> {
+    let chars: Vec<char> = query_string.chars().collect();
+    Tokenizer::from_chars(&chars).collect()
+}
 
-        // defaults to 1 if the term does not exist in the corups
-        let smoothing_default = (1 + docs_count) as f32;
-        let inverse_doc_freq = idf
-            .get_inner_map()
-            .get(&lowercase_term)
-            .unwrap_or(&smoothing_default);
-        //let inverse_doc_freq = idf.0.get(&lowercase_term).unwrap_or(&smoothing_default);
+pub fn get_current_directory() -> OsString {
+    current_dir().unwrap().into_os_string()
+}
 
-        let score = term_freq as f32 * inverse_doc_freq.log10();
+/// Version of the on-disk index layout, bumped whenever `Index` changes shape.
+pub const INDEX_FORMAT_VERSION: u32 = 9;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Index {
+    /// Document table, a document's id is its position in the table
+    pub documents: Vec<Document>,
+    pub inverted_index: InvertedIndex,
+    pub inverse_document_frequency: InverseDocumentFrequency,
+    pub average_document_length: f32,
+}
+
+impl Index {
+    pub fn new() -> Self {
         Self {
-            document_path: doc.document_path.clone(),
-            score,
+            documents: Vec::new(),
+            inverted_index: InvertedIndex::new(),
+            inverse_document_frequency: InverseDocumentFrequency::new(),
+            average_document_length: 0.0,
+        }
+    }
+
+    pub fn from_term_frequencies(tf_docs: Vec<TermFrequency>) -> Self {
+        let mut index = Self::new();
+        for tf_doc in tf_docs {
+            index.add_document(tf_doc);
+        }
+        index.update_statistics();
+        index
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn term_count(&self) -> usize {
+        self.inverted_index.get_inner_map().len()
+    }
+
+    /// Appends a document to the index. Call `update_statistics` once done adding.
+    pub fn add_document(&mut self, tf_doc: TermFrequency) -> DocumentId {
+        let document_id = self.documents.len() as DocumentId;
+        self.inverted_index.add_document(document_id, &tf_doc);
+        self.documents.push(Document {
+            path: tf_doc.document_path,
+            title: tf_doc.title,
+            length: tf_doc.length,
+            fingerprint: tf_doc.fingerprint,
+            format: tf_doc.format,
+        });
+        document_id
+    }
+
+    /// Removes documents and their postings, renumbering the remaining documents.
+    /// Call `update_statistics` once done removing.
+    pub fn remove_documents(&mut self, removed: &HashSet<DocumentId>) {
+        if removed.is_empty() {
+            return;
+        }
+
+        let mut next_id = 0;
+        let new_ids: Vec<Option<DocumentId>> = (0..self.documents.len() as DocumentId)
+            .map(|document_id| {
+                if removed.contains(&document_id) {
+                    None
+                } else {
+                    next_id += 1;
+                    Some(next_id - 1)
+                }
+            })
+            .collect();
+
+        self.inverted_index
+            .remap_documents(|document_id| new_ids[document_id as usize]);
+        let mut document_id = 0;
+        self.documents.retain(|_| {
+            document_id += 1;
+            !removed.contains(&(document_id - 1))
+        });
+    }
+
+    /// Recomputes the document frequencies and the average document length.
+    pub fn update_statistics(&mut self) {
+        self.inverse_document_frequency = InverseDocumentFrequency::from_inverted_index(
+            &self.inverted_index,
+            self.documents.len(),
+        );
+
+        let total_length: u64 = self
+            .documents
+            .iter()
+            .map(|document| document.length as u64)
+            .sum();
+        self.average_document_length = if self.documents.is_empty() {
+            0.0
+        } else {
+            total_length as f32 / self.documents.len() as f32
+        };
+    }
+}
+
+/// Read access needed to run queries, implemented by in-memory indexes and by index
+/// files that are decoded lazily.
+pub trait IndexReader {
+    fn document_count(&self) -> usize;
+    fn average_document_length(&self) -> f32;
+    fn inverse_document_frequency(&self, term: &str) -> Result<f32, anyhow::Error>;
+    fn postings(&self, term: &str) -> Result<Cow<'_, [Posting]>, anyhow::Error>;
+    /// Calls `visit` on the terms starting with `prefix` in sorted order, until it returns
+    /// `false`.
+    fn visit_terms(
+        &self,
+        prefix: &str,
+        visit: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), anyhow::Error>;
+    fn document(&self, document_id: DocumentId) -> Result<Cow<'_, Document>, anyhow::Error>;
+}
+
+impl IndexReader for Index {
+    fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+
+    fn average_document_length(&self) -> f32 {
+        self.average_document_length
+    }
+
+    fn inverse_document_frequency(&self, term: &str) -> Result<f32, anyhow::Error> {
+        Ok(self
+            .inverse_document_frequency
+            .weight(term, self.documents.len()))
+    }
+
+    fn postings(&self, term: &str) -> Result<Cow<'_, [Posting]>, anyhow::Error> {
+        Ok(Cow::Borrowed(self.inverted_index.postings(term)))
+    }
+
+    fn visit_terms(
+        &self,
+        prefix: &str,
+        visit: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), anyhow::Error> {
+        for term in self.inverted_index.terms_with_prefix(prefix) {
+            if !visit(term) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn document(&self, document_id: DocumentId) -> Result<Cow<'_, Document>, anyhow::Error> {
+        self.documents
+            .get(document_id as usize)
+            .map(Cow::Borrowed)
+            .with_context(|| format!("No document with id {document_id}"))
+    }
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads, fingerprints and tokenizes a single document.
+fn index_doc(
+    file_path: &Path,
+    parser_registry: &ParserRegistry,
+) -> Result<TermFrequency, anyhow::Error> {
+    let content =
+        fs::read(file_path).with_context(|| format!("Failed to read {}", file_path.display()))?;
+    let metadata = fs::metadata(file_path)
+        .with_context(|| format!("Failed to read metadata of {}", file_path.display()))?;
+    let parser = parser_registry.parser_for_content_of(file_path, &content)?;
+    let parsed = parser.parse(&content).with_context(|| {
+        format!(
+            "Failed to parse {} as {}",
+            file_path.display(),
+            parser.name()
+        )
+    })?;
+
+    let mut tf = TermFrequency::new(file_path.to_path_buf());
+
+    // compute TF for doc, title words are searchable as well
+    for txt in parsed.title.iter().chain([&parsed.text]) {
+        let chars: Vec<char> = txt.chars().collect();
+        for token in Tokenizer::from_chars(&chars) {
+            tf.update(&token);
         }
     }
+    tf.title = parsed.title;
+    tf.fingerprint = FileFingerprint::new(&metadata, &content);
+    tf.format = Some(parser.name().to_string());
+
+    Ok(tf)
+}
+
+/// Indexes the given files, reporting and skipping the ones that cannot be parsed.
+pub fn index_docs(file_paths: &Vec<PathBuf>, parser_registry: &ParserRegistry) -> Index {
+    let mut tf_docs = vec![];
+    for file_path in file_paths {
+        match index_doc(file_path, parser_registry) {
+            Ok(tf) => tf_docs.push(tf),
+            Err(err) => eprintln!("Skipping {}: {:#}", file_path.display(), err),
+        }
+    }
+    Index::from_term_frequencies(tf_docs)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RefreshSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Files that could not be parsed, their previous documents count as removed
+    pub failed: usize,
+}
+
+impl RefreshSummary {
+    pub fn has_changes(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
 }
 
-impl std::fmt::Display for TfIdf {
+impl std::fmt::Display for RefreshSummary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}\n\tScore: {:.2}",
-            self.document_path.display(),
-            self.score
+            "{} added, {} updated, {} removed, {} unchanged, {} failed",
+            self.added, self.updated, self.removed, self.unchanged, self.failed
         )
     }
 }
 
-pub fn get_current_directory() -> OsString {
-    current_dir().unwrap().into_os_string()
+/// Brings an index up to date with the files currently found on disk. Files whose size and
+/// modification time did not change are skipped, the others are hashed and only re-parsed
+/// when their content differs. Documents missing from `file_paths` are removed.
+pub fn refresh_index(
+    index: &mut Index,
+    file_paths: &[PathBuf],
+    parser_registry: &ParserRegistry,
+) -> RefreshSummary {
+    refresh_documents(index, |_| true, file_paths, parser_registry)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Index {
-    pub term_frequencies: Vec<TermFrequency>,
-    pub inverse_document_frequency: InverseDocumentFrequency,
+/// Like `refresh_index`, but only documents below one of `scopes` are considered, and
+/// `file_paths` holds the files currently found below them.
+pub fn refresh_index_in(
+    index: &mut Index,
+    scopes: &[PathBuf],
+    file_paths: &[PathBuf],
+    parser_registry: &ParserRegistry,
+) -> RefreshSummary {
+    refresh_documents(
+        index,
+        |path| scopes.iter().any(|scope| path.starts_with(scope)),
+        file_paths,
+        parser_registry,
+    )
 }
 
-pub fn index_docs(file_paths: &Vec<PathBuf>) -> Index {
-    let mut tf_docs = vec![];
-    for file_path in file_paths {
-        let mut file_handle = File::open(file_path).unwrap();
-        let mut input_data = String::new();
-        let _ = file_handle.read_to_string(&mut input_data).unwrap();
+fn refresh_documents<F>(
+    index: &mut Index,
+    in_scope: F,
+    file_paths: &[PathBuf],
+    parser_registry: &ParserRegistry,
+) -> RefreshSummary
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut summary = RefreshSummary::default();
+    let indexed: HashMap<PathBuf, DocumentId> = index
+        .documents
+        .iter()
+        .enumerate()
+        .map(|(document_id, document)| (document.path.clone(), document_id as DocumentId))
+        .collect();
+    let current: HashSet<&PathBuf> = file_paths.iter().collect();
 
-        let txt = parsers::parse_xml_string(input_data);
+    let mut removed: HashSet<DocumentId> = indexed
+        .iter()
+        .filter(|(path, _)| in_scope(path) && !current.contains(path))
+        .map(|(_, document_id)| *document_id)
+        .collect();
+    summary.removed = removed.len();
 
-        let chars: Vec<char> = txt.chars().collect();
-        let tokenizer = Tokenizer::from_chars(&chars);
+    let mut changed = Vec::new();
+    for file_path in file_paths {
+        let Some(&document_id) = indexed.get(file_path) else {
+            changed.push((file_path, false));
+            continue;
+        };
 
-        let mut tf = TermFrequency::new(file_path.to_path_buf());
+        let document = &mut index.documents[document_id as usize];
+        let is_unchanged = match fs::metadata(file_path) {
+            Ok(metadata) if document.fingerprint.matches_metadata(&metadata) => true,
+            Ok(metadata) => match fs::read(file_path) {
+                Ok(content)
+                    if fingerprint::content_hash(&content) == document.fingerprint.content_hash =>
+                {
+                    // only touched, keep the document but remember the new metadata
+                    document.fingerprint = FileFingerprint::new(&metadata, &content);
+                    true
+                }
+                _ => false,
+            },
+            Err(_) => false,
+        };
 
-        // compute TF for doc
-        for token in tokenizer {
-            tf.update(&token);
+        if is_unchanged {
+            summary.unchanged += 1;
+        } else {
+            removed.insert(document_id);
+            changed.push((file_path, true));
         }
-
-        tf_docs.push(tf);
     }
-    // update IDF
-    let mut idf = InverseDocumentFrequency::default();
-    for tf_doc in &tf_docs {
-        for key in tf_doc.term_freq.keys() {
-            idf.update(key, &tf_docs);
+
+    index.remove_documents(&removed);
+    for (file_path, was_indexed) in changed {
+        match index_doc(file_path, parser_registry) {
+            Ok(tf) => {
+                index.add_document(tf);
+                if was_indexed {
+                    summary.updated += 1;
+                } else {
+                    summary.added += 1;
+                }
+            }
+            Err(err) => {
+                eprintln!("Skipping {}: {:#}", file_path.display(), err);
+                summary.failed += 1;
+                if was_indexed {
+                    summary.removed += 1;
+                }
+            }
         }
     }
+    index.update_statistics();
 
-    Index {
-        term_frequencies: tf_docs,
-        inverse_document_frequency: idf,
-    }
+    summary
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
     use std::{path::PathBuf, str::FromStr};
+    use tempfile::tempdir;
 
-    use crate::utils::TfIdf;
+    use crate::parser_registry::ParserRegistry;
+    use crate::utils::{RefreshSummary, SearchResult, index_docs, refresh_index, tokenize_query};
 
     #[test]
     fn formatting_works() {
-        let tf_idf = TfIdf {
+        let tf_idf = SearchResult {
             document_path: PathBuf::from_str("a/path/buf").unwrap(),
+            title: None,
             score: 12.36163,
         };
 
@@ -118,4 +408,132 @@ mod tests {
 
         assert_eq!(result, expected)
     }
+
+    #[test]
+    fn formatting_includes_title() {
+        let result = SearchResult {
+            document_path: PathBuf::from("a/path/buf"),
+            title: Some(String::from("A title")),
+            score: 1.0,
+        };
+
+        assert_eq!(
+            format!("{}", result),
+            "a/path/buf\n\tTitle: A title\n\tScore: 1.00"
+        );
+    }
+
+    #[test]
+    fn query_is_tokenized_into_lowercase_terms() {
+        let terms = tokenize_query("Roman  Empire");
+
+        assert_eq!(terms, ["roman", "empire"]);
+    }
+
+    #[test]
+    fn refresh_only_reparses_changed_files() {
+        let temp = tempdir().unwrap();
+        let path = |name: &str| temp.path().join(name);
+        fs::write(path("kept.txt"), "ancient rome").unwrap();
+        fs::write(path("touched.txt"), "roman empire").unwrap();
+        fs::write(path("changed.txt"), "old content").unwrap();
+        fs::write(path("deleted.txt"), "carthage").unwrap();
+
+        let parser_registry = ParserRegistry::default();
+        let file_paths: Vec<PathBuf> = ["kept.txt", "touched.txt", "changed.txt", "deleted.txt"]
+            .iter()
+            .map(|name| path(name))
+            .collect();
+        let mut index = index_docs(&file_paths, &parser_registry);
+
+        // same content with a new modification time
+        let touched = fs::File::options()
+            .write(true)
+            .open(path("touched.txt"))
+            .unwrap();
+        touched
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        fs::write(path("changed.txt"), "new content about gaul").unwrap();
+        fs::remove_file(path("deleted.txt")).unwrap();
+        fs::write(path("added.txt"), "venice").unwrap();
+
+        let file_paths: Vec<PathBuf> = ["kept.txt", "touched.txt", "changed.txt", "added.txt"]
+            .iter()
+            .map(|name| path(name))
+            .collect();
+        let summary = refresh_index(&mut index, &file_paths, &parser_registry);
+
+        assert_eq!(
+            summary,
+            RefreshSummary {
+                added: 1,
+                updated: 1,
+                removed: 1,
+                unchanged: 2,
+                failed: 0
+            }
+        );
+        assert_eq!(index.document_count(), 4);
+        assert!(index.inverted_index.postings("carthage").is_empty());
+        assert!(index.inverted_index.postings("old").is_empty());
+        assert_eq!(index.inverted_index.postings("gaul").len(), 1);
+        assert_eq!(index.inverted_index.postings("venice").len(), 1);
+        assert!(
+            index
+                .inverse_document_frequency
+                .get_inner_map()
+                .contains_key("rome")
+        );
+        for term in ["rome", "empire", "gaul", "venice"] {
+            let posting = &index.inverted_index.postings(term)[0];
+            assert!((posting.document_id as usize) < index.document_count());
+        }
+    }
+
+    #[test]
+    fn files_that_fail_to_parse_are_not_counted_as_indexed() {
+        let temp = tempdir().unwrap();
+        let notes = temp.path().join("notes");
+        fs::write(&notes, "ancient rome").unwrap();
+        let parser_registry = ParserRegistry::default();
+        let mut index = index_docs(&vec![notes.clone()], &parser_registry);
+
+        // no parser recognizes the new content of the extensionless file
+        fs::write(&notes, [0xff, 0xfe, 0x00, 0x01]).unwrap();
+        let broken = temp.path().join("broken.pdf");
+        fs::write(&broken, "%PDF-1.4 truncated").unwrap();
+        let summary = refresh_index(&mut index, &[notes, broken], &parser_registry);
+
+        assert_eq!(
+            summary,
+            RefreshSummary {
+                added: 0,
+                updated: 0,
+                removed: 1,
+                unchanged: 0,
+                failed: 2
+            }
+        );
+        assert!(summary.has_changes());
+        assert_eq!(index.document_count(), 0);
+    }
+
+    #[test]
+    fn documents_record_their_parser() {
+        let temp = tempdir().unwrap();
+        let readme = temp.path().join("README");
+        fs::write(&readme, "<html><title>Rome</title>The eternal city</html>").unwrap();
+        let notes = temp.path().join("notes.txt");
+        fs::write(&notes, "ancient rome").unwrap();
+
+        let index = index_docs(&vec![readme, notes], &ParserRegistry::default());
+
+        let formats: Vec<Option<&str>> = index
+            .documents
+            .iter()
+            .map(|document| document.format.as_deref())
+            .collect();
+        assert_eq!(formats, [Some("html"), Some("text")]);
+    }
 }
@@ -1,6 +1,6 @@
-use crate::utils::TfIdf;
+use crate::utils::SearchResult;
 
-pub fn present_results_cli(mut results: Vec<TfIdf>) {
+pub fn present_results_cli(mut results: Vec<SearchResult>) {
     results.sort_by(|a, b| a.score.total_cmp(&b.score));
 
     results.reverse();
@@ -0,0 +1,330 @@
+use anyhow::{self, Context};
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::gitignore;
+use crate::parser_registry::ParserRegistry;
+use crate::path_resolver::{self, TraversalOptions};
+use crate::utils::{self, Index, RefreshSummary};
+
+/// Quiet period after the last event before a burst of changes is applied.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchBackend {
+    /// Change notifications of the operating system, inotify on Linux
+    Native,
+    /// Periodic scans of the directory tree
+    Polling,
+}
+
+impl std::fmt::Display for WatchBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchBackend::Native => write!(f, "native"),
+            WatchBackend::Polling => write!(f, "polling"),
+        }
+    }
+}
+
+/// Recursively watches a directory, falling back to polling when native change
+/// notifications are not available.
+pub struct DirectoryWatcher {
+    // events stop once the watcher is dropped
+    _watcher: Box<dyn Watcher>,
+    events: Receiver<notify::Result<Event>>,
+    root: PathBuf,
+    backend: WatchBackend,
+}
+
+impl DirectoryWatcher {
+    pub fn new<P>(
+        root: P,
+        force_polling: bool,
+        poll_interval: Duration,
+    ) -> Result<Self, anyhow::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref();
+        let (sender, events) = mpsc::channel();
+
+        if !force_polling {
+            match watch_natively(root, sender.clone()) {
+                Ok(watcher) => {
+                    return Ok(Self {
+                        _watcher: watcher,
+                        events,
+                        root: root.to_path_buf(),
+                        backend: WatchBackend::Native,
+                    });
+                }
+                Err(err) => eprintln!("Native file watching unavailable, polling instead: {err}"),
+            }
+        }
+
+        let config = Config::default().with_poll_interval(poll_interval);
+        let mut watcher = PollWatcher::new(sender, config).context("Failed to start polling.")?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+        Ok(Self {
+            _watcher: Box::new(watcher),
+            events,
+            root: root.to_path_buf(),
+            backend: WatchBackend::Polling,
+        })
+    }
+
+    pub fn backend(&self) -> WatchBackend {
+        self.backend
+    }
+
+    /// Blocks until something changes, then returns the changed paths once no further
+    /// event arrived for `debounce`. Returns `None` when the watcher stopped.
+    pub fn next_changes(&self, debounce: Duration) -> Option<Vec<PathBuf>> {
+        next_changes(&self.events, &self.root, debounce)
+    }
+}
+
+fn watch_natively(
+    root: &Path,
+    sender: mpsc::Sender<notify::Result<Event>>,
+) -> Result<Box<dyn Watcher>, notify::Error> {
+    let mut watcher = RecommendedWatcher::new(sender, Config::default())?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(Box::new(watcher))
+}
+
+fn next_changes(
+    events: &Receiver<notify::Result<Event>>,
+    root: &Path,
+    debounce: Duration,
+) -> Option<Vec<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    let mut event = events.recv().ok()?;
+    loop {
+        match event {
+            Ok(event) => changed.extend(changed_paths(root, &event)),
+            Err(err) => {
+                // events may have been lost, look at the whole tree again
+                eprintln!("File watcher error: {err}");
+                changed.insert(root.to_path_buf());
+            }
+        }
+        event = match events.recv_timeout(debounce) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) if changed.is_empty() => events.recv().ok()?,
+            Err(RecvTimeoutError::Timeout) => return Some(outermost_paths(changed)),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        };
+    }
+}
+
+/// Paths whose documents may be affected by an event. A change to an ignore file
+/// affects its whole directory.
+fn changed_paths(root: &Path, event: &Event) -> Vec<PathBuf> {
+    if event.need_rescan() {
+        return vec![root.to_path_buf()];
+    }
+    if matches!(event.kind, EventKind::Access(_)) {
+        return Vec::new();
+    }
+    event
+        .paths
+        .iter()
+        .map(|path| {
+            let is_ignore_file = path
+                .file_name()
+                .and_then(|filename| filename.to_str())
+                .is_some_and(|filename| gitignore::IGNORE_FILENAMES.contains(&filename));
+            match path.parent() {
+                Some(directory) if is_ignore_file => directory.to_path_buf(),
+                _ => path.clone(),
+            }
+        })
+        .filter(|path| path.starts_with(root))
+        .collect()
+}
+
+/// Drops the paths lying inside another path of the set.
+fn outermost_paths(paths: BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    let mut outermost: Vec<PathBuf> = Vec::new();
+    // sorted order places a directory right before its contents
+    for path in paths {
+        if !outermost.last().is_some_and(|last| path.starts_with(last)) {
+            outermost.push(path);
+        }
+    }
+    outermost
+}
+
+/// Re-indexes the files below the changed paths: new files are added, modified ones
+/// re-parsed and documents of deleted or renamed files removed.
+pub fn apply_changes(
+    index: &mut Index,
+    root: &Path,
+    changed: &[PathBuf],
+    parser_registry: &ParserRegistry,
+    options: &TraversalOptions,
+) -> Result<RefreshSummary, anyhow::Error> {
+    let mut file_paths = Vec::new();
+    for path in changed {
+        file_paths.extend(path_resolver::collect_valid_paths_in(
+            root,
+            path,
+            parser_registry,
+            options,
+        )?);
+    }
+    Ok(utils::refresh_index_in(
+        index,
+        changed,
+        &file_paths,
+        parser_registry,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+    use notify::{Event, EventKind};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    use crate::parser_registry::ParserRegistry;
+    use crate::path_resolver::TraversalOptions;
+    use crate::utils::{RefreshSummary, index_docs};
+    use crate::watcher::{apply_changes, next_changes};
+
+    fn event(kind: EventKind, paths: &[&Path]) -> notify::Result<Event> {
+        let mut event = Event::new(kind);
+        for path in paths {
+            event = event.add_path(path.to_path_buf());
+        }
+        Ok(event)
+    }
+
+    #[test]
+    fn bursts_of_events_are_debounced() {
+        let root = Path::new("/docs");
+        let (sender, events) = mpsc::channel();
+        sender
+            .send(event(
+                EventKind::Create(CreateKind::File),
+                &[&root.join("a.md")],
+            ))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Modify(ModifyKind::Any),
+                &[&root.join("a.md")],
+            ))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &[&root.join("guide/old.md"), &root.join("guide/new.md")],
+            ))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Remove(RemoveKind::Folder),
+                &[&root.join("guide")],
+            ))
+            .unwrap();
+        sender
+            .send(event(
+                EventKind::Modify(ModifyKind::Any),
+                &[&root.join("api/.gitignore")],
+            ))
+            .unwrap();
+
+        let changes = next_changes(&events, root, Duration::from_millis(10));
+
+        assert_eq!(
+            changes,
+            Some(vec![
+                root.join("a.md"),
+                root.join("api"),
+                root.join("guide")
+            ])
+        );
+    }
+
+    #[test]
+    fn closed_channel_stops_watching() {
+        let (sender, events) = mpsc::channel();
+        drop(sender);
+
+        assert_eq!(
+            next_changes(&events, Path::new("/docs"), Duration::from_millis(10)),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_are_applied_to_the_index() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir(root.join("guide")).unwrap();
+        fs::write(root.join("kept.txt"), "ancient rome").unwrap();
+        fs::write(root.join("guide/old.md"), "carthage").unwrap();
+        fs::write(root.join("edited.txt"), "draft").unwrap();
+        let parser_registry = ParserRegistry::default();
+        let mut index = index_docs(
+            &vec![
+                root.join("kept.txt"),
+                root.join("guide/old.md"),
+                root.join("edited.txt"),
+            ],
+            &parser_registry,
+        );
+
+        fs::rename(root.join("guide/old.md"), root.join("guide/new.md")).unwrap();
+        fs::write(root.join("edited.txt"), "final version").unwrap();
+        let changed: Vec<PathBuf> = vec![
+            root.join("edited.txt"),
+            root.join("guide/new.md"),
+            root.join("guide/old.md"),
+        ];
+        let summary = apply_changes(
+            &mut index,
+            root,
+            &changed,
+            &parser_registry,
+            &TraversalOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary,
+            RefreshSummary {
+                added: 1,
+                updated: 1,
+                removed: 1,
+                unchanged: 0,
+                failed: 0
+            }
+        );
+        let mut paths: Vec<&PathBuf> = index.documents.iter().map(|doc| &doc.path).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                &root.join("edited.txt"),
+                &root.join("guide/new.md"),
+                &root.join("kept.txt")
+            ]
+        );
+        assert_eq!(index.inverted_index.postings("carthage").len(), 1);
+        assert!(index.inverted_index.postings("draft").is_empty());
+    }
+}